@@ -1,132 +1,368 @@
-use bevy::transform;
-use bevy::{prelude::*, render::render_resource::Texture};
+// 帧动画
+// 动画表 (*.anim.ron) 描述图集和每个状态的片段, Animator 按状态机切换片段并推进帧
 
-use crate::player::Player;
-use crate::comm::SetUpFlag;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::bullet::BulletEvent;
+use crate::comm::{flip_sprite_by_facing, Health};
+use crate::enemy::{EnemyBrain, EnemyState};
+use crate::player::{Climber, Player, PLAYER_MOVE_STEP};
 
 pub fn animate_plugin(app: &mut App) {
     app
-        .add_event::<PlayerAnimateEvent>()
+        .add_event::<AnimationEvent>()
         .add_systems(Update, (
-            setup,
-            player_animate,
-        ))
+            (setup_player_animator, setup_enemy_animator),
+            (drive_player_animation, drive_enemy_animation),
+            (animate, flip_sprite_by_facing),
+        ).chain())
         ;
 }
 
+const PLAYER_ANIMATION: &str = "anim/player.anim.ron";
+const ENEMY_ANIMATION: &str = "anim/enemy.anim.ron";
+// 每帧实际移动超过该值 (接近全速) 时播放奔跑, 被挡住或在斜坡上减速时播放行走
+const RUN_THRESHOLD: f32 = PLAYER_MOVE_STEP * 0.75;
+const MOVE_THRESHOLD: f32 = 0.01;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimState {
+    #[default]
+    Idle,
+    Walk,
+    Run,
+    Jump,
+    Fall,
+    Climb,
+    Die,
+    Shoot,
+}
+
+impl AnimState {
+    // 播放一次后回到移动状态
+    fn is_one_shot(self) -> bool {
+        matches!(self, AnimState::Shoot)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AtlasInfo {
+    pub texture: String,
+    pub tile_size: [f32; 2],
+    pub columns: usize,
+    pub rows: usize,
+    #[serde(default)]
+    pub padding: Option<[f32; 2]>,
+    #[serde(default)]
+    pub offset: Option<[f32; 2]>,
+}
 
+impl AtlasInfo {
+    fn layout(&self) -> TextureAtlasLayout {
+        TextureAtlasLayout::from_grid(
+            Vec2::from(self.tile_size),
+            self.columns,
+            self.rows,
+            self.padding.map(Vec2::from),
+            self.offset.map(Vec2::from),
+        )
+    }
+}
 
-#[derive(Component, Clone, Default)]
-pub struct AnimationIndices {
-    first: usize,
-    last: usize,
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationClip {
+    // atlases 中的下标
+    #[serde(default)]
+    pub atlas: usize,
+    pub first: usize,
+    pub last: usize,
+    // 每帧时长, durations 中有对应帧时优先使用
+    pub frame_time: f32,
+    #[serde(default)]
+    pub durations: Vec<f32>,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    #[serde(default)]
+    pub events: Vec<FrameEvent>,
 }
 
-#[derive(Component, Deref, DerefMut)]
-pub struct AnimationTimer(Timer);
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnimEventKind {
+    Footstep,
+    // 射击动画中子弹真正射出的帧
+    ShootRelease,
+    DeathFinished,
+}
 
-#[derive(Component, Default, Clone)]
-pub struct PlayerAnimateIndices {
-    run: AnimationIndices,
-    walk: AnimationIndices,
-    jump: AnimationIndices,
-    die: AnimationIndices,
-    idle: AnimationIndices,
+// frame 为片段内的帧序号, 进入该帧时发送
+// 等于片段帧数时表示非循环片段播放结束后发送
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrameEvent {
+    pub frame: usize,
+    pub event: AnimEventKind,
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub state: AnimState,
+    pub kind: AnimEventKind,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+impl AnimationClip {
+    fn len(&self) -> usize {
+        self.last.saturating_sub(self.first) + 1
+    }
+
+    fn duration(&self, frame: usize) -> f32 {
+        self.durations.get(frame).copied().unwrap_or(self.frame_time).max(0.01)
+    }
+
+    fn events_at(&self, frame: usize) -> impl Iterator<Item = AnimEventKind> + '_ {
+        self.events.iter().filter(move |e| e.frame == frame).map(|e| e.event)
+    }
+}
+
+// 缺少的状态回退到 Idle
+#[derive(Asset, TypePath, Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationSheet {
+    pub atlases: Vec<AtlasInfo>,
+    pub clips: HashMap<AnimState, AnimationClip>,
+}
+
+impl AnimationSheet {
+    pub fn clip(&self, state: AnimState) -> Option<&AnimationClip> {
+        self.clips.get(&state).or_else(|| self.clips.get(&AnimState::Idle))
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Animator {
+    pub sheet: Handle<AnimationSheet>,
+    pub state: AnimState,
+    // 一次性动画结束后回到的状态
+    base: AnimState,
+    frame: usize,
+    timer: Timer,
+    finished: bool,
+    changed: bool,
+    layouts: Vec<Handle<TextureAtlasLayout>>,
+    textures: Vec<Handle<Image>>,
+}
+
+impl Animator {
+    pub fn new(sheet: Handle<AnimationSheet>) -> Self {
+        Animator {
+            sheet,
+            state: AnimState::Idle,
+            base: AnimState::Idle,
+            frame: 0,
+            timer: Timer::from_seconds(0., TimerMode::Once),
+            finished: false,
+            changed: true,
+            layouts: Vec::new(),
+            textures: Vec::new(),
+        }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    // 非循环的片段播放到最后一帧
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    // 由控制器每帧调用, Die 之后不再切换, 一次性动画播放期间只记录移动状态
+    pub fn request(&mut self, state: AnimState) {
+        if self.state == AnimState::Die {
+            return;
+        }
+        if state.is_one_shot() || state == AnimState::Die {
+            self.play(state);
+            return;
+        }
+        self.base = state;
+        if !(self.state.is_one_shot() && !self.finished) {
+            self.play(state);
+        }
+    }
+
+    fn play(&mut self, state: AnimState) {
+        if self.state == state && !state.is_one_shot() {
+            return;
+        }
+        self.state = state;
+        self.frame = 0;
+        self.finished = false;
+        self.changed = true;
+    }
 }
 
-pub fn setup(
+fn setup_player_animator(
     mut cmds: Commands,
     asset_server: Res<AssetServer>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    mut query_player: Query<(Entity, &mut Transform), With<Player>>,
-    mut local_data: Local<SetUpFlag>,
+    mut query_player: Query<(Entity, &Transform), (With<Player>, Without<Animator>)>,
+) {
+    for (entity, transform) in query_player.iter_mut() {
+        cmds.entity(entity).insert((
+            Animator::new(asset_server.load(PLAYER_ANIMATION)),
+            transform.with_scale(Vec3::splat(0.3)),
+        ));
+    }
+}
+
+// 关卡中的敌人和刷出的敌人都在这里加上 Animator
+fn setup_enemy_animator(
+    mut cmds: Commands,
+    asset_server: Res<AssetServer>,
+    query_enemy: Query<Entity, (With<EnemyBrain>, Without<Animator>)>,
+) {
+    for entity in query_enemy.iter() {
+        cmds.entity(entity).insert(Animator::new(asset_server.load(ENEMY_ANIMATION)));
+    }
+}
+
+fn drive_player_animation(
+    mut bullet_events: EventReader<BulletEvent>,
+    mut query: Query<(
+        &mut Animator,
+        &Climber,
+        Option<&KinematicCharacterControllerOutput>,
+        Option<&Health>,
+    ), With<Player>>,
 ) {
-    if local_data.0 {
-        return;
-    }
-    for (player_e, transform) in query_player.iter_mut() {
-        let texture: Handle<Image>  = asset_server.load("atlas/player_sheet3.png");
-        let layout = TextureAtlasLayout::from_grid(Vec2::new(350.0, 350.0), 6, 4, None, None);
-        let texture_atlas_layout: Handle<TextureAtlasLayout> = texture_atlas_layouts.add(layout);
-        let animation_indices = PlayerAnimateIndices {
-            die: AnimationIndices { first: 0, last: 5 },
-            jump: AnimationIndices { first: 6, last: 10 },
-            walk: AnimationIndices { first: 12, last: 15 },
-            run: AnimationIndices { first: 18, last: 21 },
-            ..default()
+    let shoot = bullet_events.read().count() > 0;
+    for (mut animator, climber, output, health) in query.iter_mut() {
+        let (movement, grounded) = output
+            .map(|o| (o.effective_translation, o.grounded))
+            .unwrap_or((Vec2::ZERO, true));
+
+        let state = if health.is_some_and(|h| h.is_dead()) {
+            AnimState::Die
+        } else if climber.climbing {
+            AnimState::Climb
+        } else if !grounded && movement.y > 0. {
+            AnimState::Jump
+        } else if !grounded {
+            AnimState::Fall
+        } else if movement.x.abs() > RUN_THRESHOLD {
+            AnimState::Run
+        } else if movement.x.abs() > MOVE_THRESHOLD {
+            AnimState::Walk
+        } else {
+            AnimState::Idle
         };
-        cmds.entity(player_e)
-            .insert(animation_indices.clone())
-            .insert(AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
-            .insert(TextureAtlas {
-                layout: texture_atlas_layout.clone(),
-                index: animation_indices.walk.first,
-            })
-            .insert(texture)
-            .insert(transform.with_scale(Vec3::splat(0.3)));
-        local_data.0 = true;
-    }
-}
-
-#[derive(Event)]
-pub enum PlayerAnimateEvent {
-    Run(Vec2),
-    Walk(Vec2),
-    Jump(Vec2),
-    Die(Vec2),
-    Idle(Vec2),
-}
-
-pub fn player_animate(
-    mut evt: EventReader<PlayerAnimateEvent>,
-    mut query: Query<(&PlayerAnimateIndices, &mut AnimationTimer, &mut TextureAtlas, &mut Sprite), With<Player>>,
+        animator.request(state);
+        if shoot {
+            animator.request(AnimState::Shoot);
+        }
+    }
+}
+
+fn drive_enemy_animation(
+    mut query: Query<(&mut Animator, &EnemyBrain, &Velocity, &Health), Without<Player>>,
+) {
+    for (mut animator, brain, velocity, health) in query.iter_mut() {
+        let state = if health.is_dead() {
+            AnimState::Die
+        } else {
+            match brain.state {
+                EnemyState::Attack => AnimState::Shoot,
+                EnemyState::Chase | EnemyState::Flee => AnimState::Run,
+                EnemyState::Patrol if velocity.linvel.length() > MOVE_THRESHOLD => AnimState::Walk,
+                EnemyState::Patrol | EnemyState::Stunned => AnimState::Idle,
+            }
+        };
+        // 攻击动画播放完再重新开始
+        if state != AnimState::Shoot || animator.state != AnimState::Shoot || animator.finished() {
+            animator.request(state);
+        }
+    }
+}
+
+pub fn animate(
+    mut cmds: Commands,
     time: Res<Time>,
+    sheets: Res<Assets<AnimationSheet>>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut query: Query<(Entity, &mut Animator, Option<&mut TextureAtlas>)>,
+    mut anim_events: EventWriter<AnimationEvent>,
 ) {
-    for e in evt.read() {
-        let (player_animate_indices, mut animate_timer, mut player_atlas, mut sprie) = query.single_mut();
-        animate_timer.tick(time.delta());
-        if animate_timer.just_finished() {
-            match e {
-                PlayerAnimateEvent::Run(vel) => {
-                    if player_animate_indices.run.first <= player_atlas.index && player_atlas.index <= player_animate_indices.run.last {
-                        player_atlas.index = if player_atlas.index == player_animate_indices.run.last {
-                            player_animate_indices.run.first
-                        } else {
-                            player_atlas.index + 1
-                        };
-                        
-                    } else {
-                        player_atlas.index = player_animate_indices.run.first;
-                    }
-                    println!("Run");
-                }
-                PlayerAnimateEvent::Walk(vel) => {
-                    if player_animate_indices.walk.first <= player_atlas.index && player_atlas.index <= player_animate_indices.walk.last {
-                        player_atlas.index = if player_atlas.index == player_animate_indices.walk.last {
-                            player_animate_indices.walk.first
-                        } else {
-                            player_atlas.index + 1
-                        };
-                    } else {
-                        player_atlas.index = player_animate_indices.walk.first;
-                    }
-                    if vel.x < 0. {
-                        sprie.flip_x = true;
-                    } else {
-                        sprie.flip_x = false;
+    for (entity, mut animator, atlas) in query.iter_mut() {
+        let Some(sheet) = sheets.get(&animator.sheet) else {
+            continue;
+        };
+        if animator.layouts.is_empty() {
+            for info in sheet.atlases.iter() {
+                animator.layouts.push(texture_atlas_layouts.add(info.layout()));
+                animator.textures.push(asset_server.load(info.texture.clone()));
+            }
+        }
+        let Some(clip) = sheet.clip(animator.state) else {
+            continue;
+        };
+        if clip.atlas >= animator.layouts.len() {
+            warn!("animation clip {:?} uses missing atlas {}", animator.state, clip.atlas);
+            continue;
+        }
+
+        let state = animator.state;
+        let mut send = |frame: usize| {
+            for kind in clip.events_at(frame) {
+                anim_events.send(AnimationEvent { entity, state, kind });
+            }
+        };
+        if animator.changed {
+            animator.changed = false;
+            animator.timer = Timer::from_seconds(clip.duration(0), TimerMode::Once);
+            send(0);
+        } else if !animator.finished {
+            animator.timer.tick(time.delta());
+            if animator.timer.finished() {
+                if animator.frame + 1 < clip.len() {
+                    animator.frame += 1;
+                    send(animator.frame);
+                } else if clip.looping {
+                    animator.frame = 0;
+                    send(0);
+                } else {
+                    animator.finished = true;
+                    send(clip.len());
+                    if animator.state.is_one_shot() {
+                        let base = animator.base;
+                        animator.play(base);
+                        continue;
                     }
-                    println!("Walk {}", player_atlas.index);
-                }
-                PlayerAnimateEvent::Jump(vel) => {
-                    println!("Jump");
-                }
-                PlayerAnimateEvent::Die(vel) => {
-                    println!("Die");
                 }
-                PlayerAnimateEvent::Idle(vel) => {
-                    println!("Idle");
+                let duration = clip.duration(animator.frame);
+                animator.timer = Timer::from_seconds(duration, TimerMode::Once);
+            }
+        }
+
+        let index = clip.first + animator.frame;
+        let layout = animator.layouts[clip.atlas].clone();
+        match atlas {
+            Some(mut atlas) if atlas.layout == layout => {
+                if atlas.index != index {
+                    atlas.index = index;
                 }
             }
+            _ => {
+                cmds.entity(entity).insert((
+                    TextureAtlas { layout, index },
+                    animator.textures[clip.atlas].clone(),
+                ));
+            }
         }
     }
-}
\ No newline at end of file
+}
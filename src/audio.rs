@@ -0,0 +1,313 @@
+// 音频
+// 声音分为 music, sfx, ui 三个总线, 音量来自 Settings
+// 关卡音乐来自 LDtk 关卡的 music 字段, 切换关卡时交叉淡入淡出
+// 带位置的音效按相对相机的位置左右声像并随距离衰减
+// 声音文件放在 assets/sounds, 缺少的文件加载失败后不再尝试
+
+use bevy::asset::LoadState;
+use bevy::audio::{AudioSinkPlayback, SpatialScale, Volume};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::bullet::AtkNormal;
+use crate::camera::CameraRig;
+use crate::collision::BulletHitEnemy;
+use crate::pickup::ItemPickedUpEvent;
+use crate::player::PlayerDamageEvent;
+use crate::roulette::RouletteRotateEvent;
+use crate::settings::Settings;
+
+pub fn audio_plugin(app: &mut App) {
+    app
+        .add_event::<PlaySfxEvent>()
+        .init_resource::<AudioCache>()
+        .add_systems(Update, (
+            setup_listener,
+            (sfx_hooks, change_level_music),
+            (play_sfx, fade_music),
+        ).chain());
+}
+
+// 音乐淡入淡出的时长
+const MUSIC_FADE_TIME: f32 = 1.5;
+// 两耳间距, 世界坐标
+const LISTENER_GAP: f32 = 160.;
+// 世界坐标到音频坐标的缩放, 离相机 1 / AUDIO_SCALE 像素以内不衰减
+const AUDIO_SCALE: f32 = 1. / 160.;
+// 离相机超过该距离的音效不播放
+const SFX_MAX_DISTANCE: f32 = 800.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AudioBus {
+    Music,
+    Sfx,
+    Ui,
+}
+
+impl AudioBus {
+    pub fn volume(self, settings: &Settings) -> f32 {
+        let volume = match self {
+            AudioBus::Music => settings.music_volume,
+            AudioBus::Sfx => settings.sfx_volume,
+            AudioBus::Ui => settings.ui_volume,
+        };
+        volume.clamp(0., 1.)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    Jump,
+    Shoot,
+    Hit,
+    Pickup,
+    DoorOpen,
+    RouletteRotate,
+}
+
+impl Sfx {
+    fn path(self) -> &'static str {
+        match self {
+            Sfx::Jump => "sounds/jump.ogg",
+            Sfx::Shoot => "sounds/shoot.ogg",
+            Sfx::Hit => "sounds/hit.ogg",
+            Sfx::Pickup => "sounds/pickup.ogg",
+            Sfx::DoorOpen => "sounds/door_open.ogg",
+            Sfx::RouletteRotate => "sounds/roulette_rotate.ogg",
+        }
+    }
+
+    fn bus(self) -> AudioBus {
+        match self {
+            Sfx::RouletteRotate => AudioBus::Ui,
+            _ => AudioBus::Sfx,
+        }
+    }
+}
+
+// position 为 None 时不做声像, 用于界面音效
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PlaySfxEvent {
+    pub sfx: Sfx,
+    pub position: Option<Vec2>,
+}
+
+impl PlaySfxEvent {
+    pub fn at(sfx: Sfx, position: Vec2) -> Self {
+        PlaySfxEvent { sfx, position: Some(position) }
+    }
+
+    pub fn ui(sfx: Sfx) -> Self {
+        PlaySfxEvent { sfx, position: None }
+    }
+}
+
+// 每个声音文件只加载一次, 加载失败的文件警告一次后跳过
+#[derive(Resource, Default)]
+struct AudioCache {
+    handles: HashMap<String, Handle<AudioSource>>,
+    failed: HashSet<String>,
+}
+
+impl AudioCache {
+    fn get(&mut self, asset_server: &AssetServer, path: &str) -> Option<Handle<AudioSource>> {
+        if self.failed.contains(path) {
+            return None;
+        }
+        let handle = self
+            .handles
+            .entry(path.to_string())
+            .or_insert_with(|| asset_server.load(path.to_string()))
+            .clone();
+        if asset_server.get_load_state(handle.id()) == Some(LoadState::Failed) {
+            warn!("failed to load audio {}, it will not be played", path);
+            self.handles.remove(path);
+            self.failed.insert(path.to_string());
+            return None;
+        }
+        Some(handle)
+    }
+}
+
+// 正在播放的关卡音乐, 淡出结束后删除
+#[derive(Component, Debug)]
+struct MusicTrack {
+    path: String,
+    fade: f32,
+    fading_out: bool,
+}
+
+fn setup_listener(
+    mut cmds: Commands,
+    query_camera: Query<Entity, Added<CameraRig>>,
+) {
+    for entity in query_camera.iter() {
+        cmds.entity(entity).insert(SpatialListener::new(LISTENER_GAP));
+    }
+}
+
+// 把已有的游戏事件转换成音效
+fn sfx_hooks(
+    mut damage_events: EventReader<PlayerDamageEvent>,
+    mut hit_events: EventReader<BulletHitEnemy>,
+    mut picked_events: EventReader<ItemPickedUpEvent>,
+    mut rotate_events: EventReader<RouletteRotateEvent>,
+    query_shot: Query<&Transform, Added<AtkNormal>>,
+    query_transform: Query<&GlobalTransform>,
+    mut sfx_events: EventWriter<PlaySfxEvent>,
+) {
+    let position = |entity: Entity| query_transform.get(entity).ok().map(|t| t.translation().truncate());
+    for transform in query_shot.iter() {
+        sfx_events.send(PlaySfxEvent::at(Sfx::Shoot, transform.translation.truncate()));
+    }
+    for event in damage_events.read() {
+        sfx_events.send(PlaySfxEvent { sfx: Sfx::Hit, position: position(event.target) });
+    }
+    for event in hit_events.read() {
+        sfx_events.send(PlaySfxEvent { sfx: Sfx::Hit, position: position(event.enemy) });
+    }
+    for event in picked_events.read() {
+        sfx_events.send(PlaySfxEvent::at(Sfx::Pickup, event.position));
+    }
+    for _ in rotate_events.read() {
+        sfx_events.send(PlaySfxEvent::ui(Sfx::RouletteRotate));
+    }
+}
+
+fn play_sfx(
+    mut cmds: Commands,
+    mut events: EventReader<PlaySfxEvent>,
+    asset_server: Res<AssetServer>,
+    mut cache: ResMut<AudioCache>,
+    settings: Res<Settings>,
+    query_camera: Query<&GlobalTransform, With<SpatialListener>>,
+) {
+    let listener = query_camera.get_single().ok().map(|t| t.translation().truncate());
+    for event in events.read() {
+        let volume = event.sfx.bus().volume(&settings);
+        if volume <= 0. {
+            continue;
+        }
+        let Some(source) = cache.get(&asset_server, event.sfx.path()) else {
+            continue;
+        };
+        let settings = PlaybackSettings::DESPAWN.with_volume(Volume::new(volume));
+        match (event.position, listener) {
+            (Some(position), Some(listener)) => {
+                if position.distance(listener) > SFX_MAX_DISTANCE {
+                    continue;
+                }
+                cmds.spawn((
+                    AudioBundle {
+                        source,
+                        settings: settings
+                            .with_spatial(true)
+                            .with_spatial_scale(SpatialScale::new_2d(AUDIO_SCALE)),
+                    },
+                    SpatialBundle::from_transform(Transform::from_translation(position.extend(0.))),
+                    Name::new(format!("sfx_{:?}", event.sfx)),
+                ));
+            }
+            _ => {
+                cmds.spawn((
+                    AudioBundle { source, settings },
+                    Name::new(format!("sfx_{:?}", event.sfx)),
+                ));
+            }
+        }
+    }
+}
+
+// 相机进入新关卡时切换到该关卡的音乐, 没有设置 music 时继续播放当前音乐, 设为空字符串时静音
+fn change_level_music(
+    mut cmds: Commands,
+    asset_server: Res<AssetServer>,
+    mut cache: ResMut<AudioCache>,
+    query_rig: Query<&CameraRig>,
+    mut query_music: Query<&mut MusicTrack>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut current: Local<Option<LevelIid>>,
+    mut warned_unset: Local<bool>,
+) {
+    let Some(level_iid) = query_rig.get_single().ok().and_then(|rig| rig.level()) else {
+        return;
+    };
+    if current.as_ref() == Some(level_iid) {
+        return;
+    }
+    let Some(level) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+        .and_then(|project| project.get_raw_level_by_iid(&level_iid.to_string()))
+    else {
+        return;
+    };
+    *current = Some(level_iid.clone());
+
+    let Some(path) = level.get_maybe_string_field("music").ok().cloned().flatten() else {
+        if !*warned_unset {
+            *warned_unset = true;
+            warn!("level {} has no music set, keeping the current music", level.identifier);
+        }
+        return;
+    };
+    let mut playing = false;
+    for mut track in query_music.iter_mut() {
+        if track.path == path {
+            // 正在淡出的同一首音乐重新淡入
+            track.fading_out = false;
+            playing = true;
+        } else {
+            track.fading_out = true;
+        }
+    }
+    if playing || path.is_empty() {
+        return;
+    }
+    let Some(source) = cache.get(&asset_server, &path) else {
+        return;
+    };
+    cmds.spawn((
+        AudioBundle {
+            source,
+            settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.)),
+        },
+        Name::new(format!("music_{}", path)),
+        MusicTrack {
+            path,
+            fade: 0.,
+            fading_out: false,
+        },
+    ));
+}
+
+// 淡入淡出, 同时让音乐音量跟随设置
+fn fade_music(
+    mut cmds: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut query: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+) {
+    let step = time.delta_seconds() / MUSIC_FADE_TIME;
+    let bus = AudioBus::Music.volume(&settings);
+    for (entity, mut track, sink) in query.iter_mut() {
+        if track.fading_out {
+            track.fade = (track.fade - step).max(0.);
+            if track.fade <= 0. {
+                cmds.entity(entity).despawn_recursive();
+                continue;
+            }
+        } else {
+            track.fade = (track.fade + step).min(1.);
+        }
+        if let Some(sink) = sink {
+            let volume = track.fade * bus;
+            if sink.volume() != volume {
+                sink.set_volume(volume);
+            }
+        }
+    }
+}
@@ -0,0 +1,561 @@
+// Boss 战
+// Boss 也是 Enemy, 子弹命中和扣血沿用 enemy 的处理, 这里只负责阶段, 攻击方式, 场地封锁和血条
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::bullet::spawn_atk_enemy;
+use crate::camera::CameraShakeEvent;
+use crate::collision::Layer;
+use crate::comm::Health;
+use crate::enemy::{Enemy, EnemyKilledEvent, EnemyKind};
+use crate::global::Score;
+use crate::locale::LocalizedText;
+use crate::player::{Player, PlayerDamageEvent};
+use crate::scene::{ColliderBundle, Door};
+use crate::spawner::{spawn_enemy, SpawnedBy};
+
+pub fn boss_plugin(app: &mut App) {
+    app
+        .add_event::<BossPhaseEvent>()
+        .add_event::<BossDefeatedEvent>()
+        .add_systems(Update, (
+            start_boss_fight,
+            boss_phase,
+            boss_attack,
+            update_boss_bar,
+            end_boss_fight,
+        ).chain());
+}
+
+const BOSS_HEALTH: f32 = 30.;
+const BOSS_DEFEAT_SCORE: u32 = 1000;
+const BOSS_ARENA_SIZE: Vec2 = Vec2::new(320., 180.);
+const BOSS_BULLET_SIZE: f32 = 6.;
+const BOSS_BULLET_DAMAGE: f32 = 1.;
+// 冲撞时碰到玩家的距离和伤害
+const BOSS_CONTACT_RANGE: f32 = 24.;
+const BOSS_CONTACT_DAMAGE: f32 = 2.;
+// 冲撞撞到场地边缘时的屏幕震动
+const BOSS_CHARGE_TRAUMA: f32 = 0.5;
+// 同时存在的召唤物上限
+const BOSS_MAX_SUMMONS: usize = 4;
+const BOSS_SUMMON_HEALTH: f32 = 2.;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttackPattern {
+    // 朝玩家方向扇形发射子弹, angle 为扇形总角度, 为 TAU 时是一圈
+    Spread { count: u32, angle: f32, speed: f32 },
+    // 朝玩家冲撞一段时间
+    Charge { speed: f32, duration: f32 },
+    // 在身边召唤小怪
+    Summon { kind: EnemyKind, count: u32 },
+}
+
+// 血量比例不高于 threshold 时进入该阶段, 按顺序轮流使用 patterns
+#[derive(Clone, Debug, PartialEq)]
+pub struct BossPhase {
+    pub threshold: f32,
+    pub interval: f32,
+    pub patterns: Vec<AttackPattern>,
+}
+
+// 内置的阶段脚本, 超出部分沿用最后一个阶段的攻击方式
+fn phase_script(index: usize, threshold: f32) -> BossPhase {
+    let (interval, patterns) = match index {
+        0 => (2., vec![
+            AttackPattern::Spread { count: 5, angle: 1., speed: 120. },
+            AttackPattern::Charge { speed: 200., duration: 0.6 },
+        ]),
+        1 => (1.6, vec![
+            AttackPattern::Spread { count: 7, angle: 1.4, speed: 130. },
+            AttackPattern::Summon { kind: EnemyKind::Walker, count: 2 },
+            AttackPattern::Charge { speed: 220., duration: 0.7 },
+        ]),
+        _ => (1.2, vec![
+            AttackPattern::Spread { count: 16, angle: TAU, speed: 140. },
+            AttackPattern::Summon { kind: EnemyKind::Flyer, count: 2 },
+            AttackPattern::Charge { speed: 260., duration: 0.8 },
+        ]),
+    };
+    BossPhase {
+        threshold,
+        interval,
+        patterns,
+    }
+}
+
+// 血量比例对应的阶段, phases 按 threshold 从高到低排列
+pub fn phase_for_health(phases: &[BossPhase], fraction: f32) -> usize {
+    phases
+        .iter()
+        .rposition(|phase| fraction <= phase.threshold)
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Charge {
+    pub(crate) velocity: Vec2,
+    pub(crate) timer: Timer,
+    pub(crate) hit: bool,
+}
+
+#[derive(Clone, Component, Debug)]
+pub struct Boss {
+    pub phases: Vec<BossPhase>,
+    pub phase: usize,
+    // 场地大小, 以 Boss 初始位置为中心
+    pub arena: Vec2,
+    pub(crate) origin: Option<Vec2>,
+    pub(crate) pattern: usize,
+    pub(crate) attack_timer: Timer,
+    pub(crate) charge: Option<Charge>,
+}
+
+impl Boss {
+    pub fn new(thresholds: &[f32], arena: Vec2) -> Self {
+        let mut thresholds = thresholds.to_vec();
+        thresholds.sort_by(|a, b| b.total_cmp(a));
+        let phases = std::iter::once(1.)
+            .chain(thresholds.into_iter().filter(|t| *t < 1.))
+            .enumerate()
+            .map(|(i, threshold)| phase_script(i, threshold))
+            .collect::<Vec<_>>();
+        let interval = phases[0].interval;
+        Boss {
+            phases,
+            phase: 0,
+            arena,
+            origin: None,
+            pattern: 0,
+            attack_timer: Timer::from_seconds(interval, TimerMode::Once),
+            charge: None,
+        }
+    }
+
+    pub fn current_phase(&self) -> &BossPhase {
+        &self.phases[self.phase.min(self.phases.len() - 1)]
+    }
+
+    // 除第一阶段外各阶段的血量比例, 传给 new 可以得到同样的阶段
+    pub fn thresholds(&self) -> Vec<f32> {
+        self.phases.iter().skip(1).map(|phase| phase.threshold).collect()
+    }
+}
+
+impl Default for Boss {
+    fn default() -> Self {
+        Boss::new(&[0.6, 0.3], BOSS_ARENA_SIZE)
+    }
+}
+
+// LDtk 字段: phases 为各阶段的血量比例, arena_width / arena_height 为场地大小
+impl From<&EntityInstance> for Boss {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let field = |name: &str| {
+            entity_instance
+                .get_maybe_float_field(name)
+                .ok()
+                .copied()
+                .flatten()
+        };
+        let arena = Vec2::new(
+            field("arena_width").unwrap_or(BOSS_ARENA_SIZE.x),
+            field("arena_height").unwrap_or(BOSS_ARENA_SIZE.y),
+        );
+        match entity_instance.get_floats_field("phases") {
+            Ok(thresholds) => Boss::new(&thresholds.iter().flatten().copied().collect::<Vec<_>>(), arena),
+            Err(_) => Boss {
+                arena,
+                ..default()
+            },
+        }
+    }
+}
+
+fn boss_health(entity_instance: &EntityInstance) -> Health {
+    entity_instance
+        .get_maybe_float_field("health")
+        .ok()
+        .copied()
+        .flatten()
+        .map(Health::new)
+        .unwrap_or(Health::new(BOSS_HEALTH))
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct BossBundle {
+    #[sprite_sheet_bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    pub enemy: Enemy,
+    #[from_entity_instance]
+    pub boss: Boss,
+    #[with(boss_health)]
+    pub health: Health,
+}
+
+// 正在进行的 Boss 战, arena 为场地范围, 只封锁场地内的门
+#[derive(Resource, Clone, Debug)]
+pub struct BossFight {
+    pub boss: Entity,
+    pub arena: Rect,
+}
+
+// 在运行时生成 Boss, 用于快照恢复被消灭的 Boss
+pub fn spawn_boss(cmds: &mut Commands, translation: Vec3, boss: Boss, health: Health) -> Entity {
+    cmds.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::CRIMSON,
+                custom_size: Some(Vec2::splat(32.)),
+                ..default()
+            },
+            transform: Transform::from_translation(translation),
+            ..default()
+        },
+        ColliderBundle {
+            collider: Collider::cuboid(16., 16.),
+            rigid_body: RigidBody::KinematicVelocityBased,
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            ..default()
+        }.with_layer(Layer::Enemy),
+        Enemy,
+        boss,
+        health,
+        Name::new("boss"),
+    )).id()
+}
+
+// 直接设置 Boss 战状态, 用于快照恢复; 门的开关由快照单独恢复
+pub fn set_boss_fight(world: &mut World, fight: Option<BossFight>) {
+    let bars: Vec<Entity> = world.query_filtered::<Entity, With<BossBar>>().iter(world).collect();
+    match fight {
+        Some(fight) => {
+            world.insert_resource(fight);
+            if bars.is_empty() {
+                let asset_server = world.get_resource::<AssetServer>().cloned();
+                let mut queue = bevy::ecs::system::CommandQueue::default();
+                let mut cmds = Commands::new(&mut queue, world);
+                spawn_boss_bar(&mut cmds, asset_server.as_ref());
+                queue.apply(world);
+            }
+        }
+        None => {
+            world.remove_resource::<BossFight>();
+            for entity in bars {
+                world.entity_mut(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct BossPhaseEvent {
+    pub boss: Entity,
+    pub phase: usize,
+}
+
+#[derive(Event)]
+pub struct BossDefeatedEvent {
+    pub boss: Entity,
+    pub position: Vec2,
+}
+
+#[derive(Component)]
+struct BossBar;
+
+#[derive(Component)]
+struct BossBarFill;
+
+fn set_doors_locked(query_door: &mut Query<(&mut Door, &GlobalTransform)>, arena: Rect, locked: bool) {
+    for (mut door, transform) in query_door.iter_mut() {
+        if door.locked != locked && arena.contains(transform.translation().truncate()) {
+            door.locked = locked;
+        }
+    }
+}
+
+// 玩家进入场地后关门, 显示血条
+fn start_boss_fight(
+    mut cmds: Commands,
+    fight: Option<Res<BossFight>>,
+    query_player: Query<&GlobalTransform, With<Player>>,
+    mut query_boss: Query<(Entity, &mut Boss, Ref<GlobalTransform>)>,
+    mut query_door: Query<(&mut Door, &GlobalTransform)>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok(player_transform) = query_player.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation().truncate();
+    for (entity, mut boss, transform) in query_boss.iter_mut() {
+        // 刚生成时 GlobalTransform 还没有更新
+        if transform.is_added() {
+            continue;
+        }
+        let origin = *boss.origin.get_or_insert(transform.translation().truncate());
+        if fight.is_some() {
+            continue;
+        }
+        let offset = (player_pos - origin).abs();
+        if offset.x > boss.arena.x / 2. || offset.y > boss.arena.y / 2. {
+            continue;
+        }
+
+        let arena = Rect::from_center_size(origin, boss.arena);
+        cmds.insert_resource(BossFight { boss: entity, arena });
+        set_doors_locked(&mut query_door, arena, true);
+        spawn_boss_bar(&mut cmds, Some(&asset_server));
+        return;
+    }
+}
+
+fn spawn_boss_bar(cmds: &mut Commands, asset_server: Option<&AssetServer>) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(16.),
+                left: Val::Percent(25.),
+                width: Val::Percent(50.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        },
+        BossBar,
+        Name::new("boss_bar"),
+    )).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.map(|a| a.load("fonts/FiraSans-Bold.ttf")).unwrap_or_default(),
+                    font_size: 20.,
+                    color: Color::WHITE,
+                },
+            ),
+            LocalizedText::new("boss.title"),
+        ));
+        parent.spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Px(12.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                },
+                BossBarFill,
+            ));
+        });
+    });
+}
+
+fn boss_phase(
+    fight: Option<Res<BossFight>>,
+    mut query_boss: Query<(Entity, &mut Boss, &Health)>,
+    mut phase_event: EventWriter<BossPhaseEvent>,
+) {
+    let Some(fight) = fight else {
+        return;
+    };
+    let Ok((entity, mut boss, health)) = query_boss.get_mut(fight.boss) else {
+        return;
+    };
+    let phase = phase_for_health(&boss.phases, health.fraction());
+    if phase > boss.phase {
+        boss.phase = phase;
+        boss.pattern = 0;
+        let interval = boss.current_phase().interval;
+        boss.attack_timer = Timer::from_seconds(interval, TimerMode::Once);
+        phase_event.send(BossPhaseEvent {
+            boss: entity,
+            phase,
+        });
+    }
+}
+
+// 依次执行当前阶段的攻击方式
+fn boss_attack(
+    mut cmds: Commands,
+    time: Res<Time>,
+    fight: Option<Res<BossFight>>,
+    query_player: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut query_boss: Query<(Entity, &mut Boss, &GlobalTransform, &Transform, &mut Velocity, Option<&Parent>)>,
+    query_summoned: Query<&SpawnedBy, With<Enemy>>,
+    mut damage_event: EventWriter<PlayerDamageEvent>,
+    mut shake_event: EventWriter<CameraShakeEvent>,
+) {
+    let Some(fight) = fight else {
+        return;
+    };
+    let Ok((player, player_transform)) = query_player.get_single() else {
+        return;
+    };
+    let Ok((entity, mut boss, global_transform, transform, mut velocity, parent)) = query_boss.get_mut(fight.boss) else {
+        return;
+    };
+    let pos = global_transform.translation().truncate();
+    let player_pos = player_transform.translation().truncate();
+    let dir = (player_pos - pos).normalize_or_zero();
+
+    let origin = boss.origin.unwrap_or(pos);
+    let half_arena = boss.arena / 2.;
+    if let Some(charge) = boss.charge.as_mut() {
+        charge.timer.tick(time.delta());
+        velocity.linvel = charge.velocity;
+        if !charge.hit && pos.distance(player_pos) <= BOSS_CONTACT_RANGE {
+            charge.hit = true;
+            damage_event.send(PlayerDamageEvent {
+                source: entity,
+                target: player,
+                damage: BOSS_CONTACT_DAMAGE,
+            });
+        }
+        // 冲出场地前停下
+        let next = (pos + charge.velocity * time.delta_seconds() - origin).abs();
+        let inside = next.x <= half_arena.x && next.y <= half_arena.y;
+        if inside && !charge.timer.finished() {
+            return;
+        }
+        // 撞到场地边缘
+        if !inside {
+            shake_event.send(CameraShakeEvent { trauma: BOSS_CHARGE_TRAUMA });
+        }
+        boss.charge = None;
+    }
+    velocity.linvel = Vec2::ZERO;
+
+    boss.attack_timer.tick(time.delta());
+    if !boss.attack_timer.finished() {
+        return;
+    }
+    let phase = boss.current_phase().clone();
+    let pattern = &phase.patterns[boss.pattern % phase.patterns.len()];
+    boss.pattern += 1;
+    boss.attack_timer = Timer::from_seconds(phase.interval, TimerMode::Once);
+
+    match *pattern {
+        AttackPattern::Spread { count, angle, speed } => {
+            let base = dir.y.atan2(dir.x);
+            // 一整圈时首尾不重合
+            let step = if angle >= TAU {
+                TAU / count as f32
+            } else if count > 1 {
+                angle / (count - 1) as f32
+            } else {
+                0.
+            };
+            let start = if angle >= TAU { base } else { base - angle / 2. };
+            for i in 0..count {
+                let (sin, cos) = (start + step * i as f32).sin_cos();
+                let bullet_dir = Vec2::new(cos, sin);
+                let spawn_at = global_transform.translation() + (bullet_dir * BOSS_CONTACT_RANGE).extend(0.);
+                spawn_atk_enemy(
+                    &mut cmds,
+                    &Transform::from_translation(spawn_at),
+                    bullet_dir * speed,
+                    Vec2::splat(BOSS_BULLET_SIZE),
+                    BOSS_BULLET_DAMAGE,
+                );
+            }
+        }
+        AttackPattern::Charge { speed, duration } => {
+            boss.charge = Some(Charge {
+                velocity: dir * speed,
+                timer: Timer::from_seconds(duration, TimerMode::Once),
+                hit: false,
+            });
+        }
+        AttackPattern::Summon { kind, count } => {
+            let alive = query_summoned.iter().filter(|s| s.0 == entity).count();
+            let count = (count as usize).min(BOSS_MAX_SUMMONS.saturating_sub(alive));
+            for i in 0..count {
+                let side = if i % 2 == 0 { 1. } else { -1. };
+                let offset = Vec3::new(side * BOSS_CONTACT_RANGE * (1 + i / 2) as f32, 0., 0.);
+                let summon = spawn_enemy(&mut cmds, kind, transform.translation + offset, BOSS_SUMMON_HEALTH, None);
+                cmds.entity(summon).insert(SpawnedBy(entity));
+                if let Some(parent) = parent {
+                    cmds.entity(parent.get()).add_child(summon);
+                }
+            }
+        }
+    }
+}
+
+fn update_boss_bar(
+    fight: Option<Res<BossFight>>,
+    query_boss: Query<&Health, With<Boss>>,
+    mut query_fill: Query<&mut Style, With<BossBarFill>>,
+) {
+    let Some(fight) = fight else {
+        return;
+    };
+    let Ok(health) = query_boss.get(fight.boss) else {
+        return;
+    };
+    for mut style in query_fill.iter_mut() {
+        style.width = Val::Percent(health.fraction() * 100.);
+    }
+}
+
+// Boss 被消灭或随关卡卸载后开门, 清理召唤物和血条
+fn end_boss_fight(
+    mut cmds: Commands,
+    fight: Option<Res<BossFight>>,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    query_boss: Query<(), With<Boss>>,
+    query_summoned: Query<(Entity, &SpawnedBy)>,
+    query_bar: Query<Entity, With<BossBar>>,
+    mut query_door: Query<(&mut Door, &GlobalTransform)>,
+    mut score: ResMut<Score>,
+    mut defeated_event: EventWriter<BossDefeatedEvent>,
+) {
+    let Some(fight) = fight else {
+        killed_events.clear();
+        return;
+    };
+    // 读完所有事件, 下一帧不会重复处理
+    let killed = killed_events
+        .read()
+        .filter(|e| e.enemy == fight.boss)
+        .map(|e| e.position)
+        .last();
+    if let Some(position) = killed {
+        score.0 += BOSS_DEFEAT_SCORE;
+        defeated_event.send(BossDefeatedEvent {
+            boss: fight.boss,
+            position,
+        });
+    } else if query_boss.contains(fight.boss) {
+        return;
+    }
+
+    for (entity, spawned_by) in query_summoned.iter() {
+        if spawned_by.0 == fight.boss {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+    for entity in query_bar.iter() {
+        cmds.entity(entity).despawn_recursive();
+    }
+    set_doors_locked(&mut query_door, fight.arena, false);
+    cmds.remove_resource::<BossFight>();
+}
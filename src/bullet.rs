@@ -1,65 +1,174 @@
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_rapier2d::prelude::*;
 
-use crate::roulette::{self, Roulette, RouletteItem};
+use crate::animate::{AnimEventKind, AnimationEvent};
+use crate::comm::Facing;
+use crate::collision::{BulletHitEnemy, EnemyBulletHit, Layer};
+use crate::enemy::EnemyHitEvent;
+use crate::player::{Player, PlayerDamageEvent};
+use crate::roulette::{Roulette, RouletteConsumeEvent, RouletteSpinSettledEvent};
 
 pub fn bullet_plugin(app: &mut App) {
     app
     .add_event::<BulletEvent>()
+    .init_resource::<LoadedItem>()
+    .init_resource::<PendingShots>()
     .add_systems(Update, (
         run,
+        load_spin_item,
+        queue_shots,
         bullet_linstener,
-    ));
+    ).chain());
 }
 
+// 射击动画没有 ShootRelease 帧时, 最多等待这么久就直接射出
+const SHOT_RELEASE_TIMEOUT: f32 = 0.3;
+const PLAYER_BULLET_SPEED: f32 = 1500.;
+const PLAYER_BULLET_SIZE: Vec2 = Vec2::new(70., 70.);
+// 子弹生成在离玩家这么远的位置
+const BULLET_SPAWN_DISTANCE: f32 = 100.;
+// 子弹偏向朝向一侧, 竖直瞄准时也不会出现在头顶
+const BULLET_SIDE_OFFSET: f32 = 0.3;
+
 #[derive(Component)]
 pub struct AtkNormal;
 
+// 敌人发射的子弹
+#[derive(Component)]
+pub struct AtkEnemy {
+    pub damage: f32,
+}
+
+const ATK_NORMAL_DAMAGE: f32 = 1.;
+
+// 子弹携带的轮盘物品
+#[derive(Component)]
+pub struct BulletItem {
+    pub id: u32,
+}
+
+// 转盘抽中的物品装填到下一发子弹, 这一发不消耗次数
+#[derive(Resource, Default)]
+pub struct LoadedItem(pub Option<u32>);
+
+fn load_spin_item(
+    mut events: EventReader<RouletteSpinSettledEvent>,
+    mut loaded: ResMut<LoadedItem>,
+) {
+    for event in events.read() {
+        loaded.0 = Some(event.id);
+    }
+}
+
 pub fn run(
-    mut collision_events: EventReader<CollisionEvent>,
-    query: Query<Entity, With<AtkNormal>>,
+    mut cmds: Commands,
+    mut hit_enemy_events: EventReader<BulletHitEnemy>,
+    mut enemy_bullet_events: EventReader<EnemyBulletHit>,
+    query_enemy_bullet: Query<&AtkEnemy>,
+    query_player: Query<Entity, With<Player>>,
+    mut hit_event: EventWriter<EnemyHitEvent>,
+    mut damage_event: EventWriter<PlayerDamageEvent>,
 ) {
-    for event in collision_events.read() {
-        match event {
-            CollisionEvent::Started(entity1, entity2, _flags)
-            | CollisionEvent::Stopped(entity1, entity2, _flags) => {
-                let bullet_entity = if query.contains(*entity1) {
-                    *entity1
-                } else if query.contains(*entity2) {
-                    *entity2
-                } else {
-                    continue;
-                };
-                let _other_entity = if bullet_entity == *entity1 {
-                    *entity2
-                } else {
-                    *entity1
-                };
-            }
+    let mut despawned = HashSet::new();
+    for event in hit_enemy_events.read() {
+        if !despawned.insert(event.bullet) {
+            continue;
         }
+        hit_event.send(EnemyHitEvent {
+            enemy: event.enemy,
+            damage: ATK_NORMAL_DAMAGE,
+        });
+        cmds.entity(event.bullet).despawn_recursive();
+    }
+
+    // 敌人的子弹碰到除敌人以外的东西就消失
+    for event in enemy_bullet_events.read() {
+        let Ok(bullet) = query_enemy_bullet.get(event.bullet) else {
+            continue;
+        };
+        if !despawned.insert(event.bullet) {
+            continue;
+        }
+        if query_player.contains(event.other) {
+            damage_event.send(PlayerDamageEvent {
+                source: event.bullet,
+                target: event.other,
+                damage: bullet.damage,
+            });
+        }
+        cmds.entity(event.bullet).despawn_recursive();
     }
 }
 
-#[derive(Event)]
+// 玩家射击, direction 为瞄准方向的单位向量
+// 生成位置在出手时按射击者当时的位置计算
+#[derive(Event, Clone)]
 pub struct BulletEvent {
-    pub transform: Transform,
-    pub vel: Vec2,
+    pub shooter: Entity,
+    pub direction: Vec2,
 }
 
-fn bullet_linstener(
-    mut cmds: Commands,
+// 按射击者当前的位置和朝向计算子弹的生成位置
+fn shot_transform(shooter: &Transform, facing: Option<&Facing>, direction: Vec2) -> Transform {
+    let side = facing.map_or(0., |facing| facing.sign());
+    let offset = (direction + Vec2::X * side * BULLET_SIDE_OFFSET).normalize_or_zero() * BULLET_SPAWN_DISTANCE;
+    Transform::from_translation(shooter.translation + offset.extend(0.))
+}
+
+// 等待射击动画到达出手帧的子弹
+#[derive(Resource, Default)]
+pub struct PendingShots(Vec<(BulletEvent, Timer)>);
+
+fn queue_shots(
     mut events: EventReader<BulletEvent>,
-    roulette: Query<(&Roulette)>,
-    roulette_item: Query<(&Transform, &Handle<Image>), With<RouletteItem>>,
+    mut pending: ResMut<PendingShots>,
 ) {
     for event in events.read() {
-        let sprite_size = Vec2::new(70., 70.);
-        let transform = event.transform;
-        let vel = event.vel;
+        pending.0.push((event.clone(), Timer::from_seconds(SHOT_RELEASE_TIMEOUT, TimerMode::Once)));
+    }
+}
+
+// 玩家射击动画的 ShootRelease 帧或等待超时后生成子弹
+fn bullet_linstener(
+    mut cmds: Commands,
+    time: Res<Time>,
+    mut anim_events: EventReader<AnimationEvent>,
+    mut pending: ResMut<PendingShots>,
+    query_player: Query<(&Transform, Option<&Facing>), With<Player>>,
+    roulette: Query<&Roulette>,
+    mut loaded: ResMut<LoadedItem>,
+    mut consume_event: EventWriter<RouletteConsumeEvent>,
+) {
+    let released = anim_events
+        .read()
+        .filter(|e| e.kind == AnimEventKind::ShootRelease && query_player.contains(e.entity))
+        .count();
+    for (_, timer) in pending.0.iter_mut() {
+        timer.tick(time.delta());
+    }
+    let ready = pending.0.iter().take_while(|(_, timer)| timer.finished()).count();
+    let count = ready.max(released).min(pending.0.len());
+
+    for (event, _) in pending.0.drain(..count) {
+        // 等待期间玩家已经不存在
+        let Ok((shooter, facing)) = query_player.get(event.shooter) else {
+            continue;
+        };
+        let transform = shot_transform(shooter, facing, event.direction);
+        let vel = event.direction * PLAYER_BULLET_SPEED;
         let roulette = roulette.single();
-        if let Some(cur_item) = roulette.get_cur_item() {
+        let item = match loaded.0.take() {
+            Some(id) => Some(id),
+            None => roulette.get_cur_item().map(|slot| {
+                consume_event.send(RouletteConsumeEvent);
+                slot.id
+            }),
+        };
+        let bullet = spawn_atk_normal(&mut cmds, &transform, vel, PLAYER_BULLET_SIZE);
+        if let Some(id) = item {
+            cmds.entity(bullet).insert(BulletItem { id });
         }
-        spawn_atk_normal(&mut cmds, &transform, vel, sprite_size);
     }
 }
 
@@ -68,12 +177,40 @@ pub fn spawn_atk_normal(
     transform: &Transform,
     vel: Vec2,
     sprite_size: Vec2,
-) {
+) -> Entity {
+    let bullet = spawn_bullet(cmds, transform, vel, sprite_size, Color::RED, Layer::PlayerBullet);
+    cmds.entity(bullet).insert((Name::new("AtkNormal"), AtkNormal));
+    bullet
+}
+
+pub fn spawn_atk_enemy(
+    cmds: &mut Commands,
+    transform: &Transform,
+    vel: Vec2,
+    sprite_size: Vec2,
+    damage: f32,
+) -> Entity {
+    let bullet = spawn_bullet(cmds, transform, vel, sprite_size, Color::PURPLE, Layer::EnemyBullet);
+    cmds.entity(bullet).insert((
+        Name::new("AtkEnemy"),
+        AtkEnemy { damage },
+        GravityScale(0.),
+    ));
+    bullet
+}
+
+fn spawn_bullet(
+    cmds: &mut Commands,
+    transform: &Transform,
+    vel: Vec2,
+    sprite_size: Vec2,
+    color: Color,
+    layer: Layer,
+) -> Entity {
     cmds.spawn((
-        Name::new("AtkNormal"),
         SpriteBundle {
             sprite: Sprite {
-                color: Color::RED,
+                color,
                 custom_size: Some(sprite_size),
                 ..default()
             },
@@ -81,10 +218,31 @@ pub fn spawn_atk_normal(
             ..default()
         },
         // Sensor,
-        AtkNormal,
         RigidBody::Dynamic,
         Velocity::linear(vel),
         Collider::cuboid(sprite_size.x/2., sprite_size.y/2.),
         ActiveEvents::COLLISION_EVENTS,
-    ));
-}
\ No newline at end of file
+        layer.groups(),
+    )).id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shot_spawns_from_current_shooter_position() {
+        let direction = Vec2::X;
+        let before = shot_transform(&Transform::from_xyz(0., 0., 5.), None, direction);
+        let after = shot_transform(&Transform::from_xyz(10., 40., 5.), None, direction);
+        assert_eq!(before.translation, Vec3::new(BULLET_SPAWN_DISTANCE, 0., 5.));
+        assert_eq!(after.translation, Vec3::new(10. + BULLET_SPAWN_DISTANCE, 40., 5.));
+    }
+
+    #[test]
+    fn vertical_shot_leans_to_facing_side() {
+        let transform = shot_transform(&Transform::default(), Some(&Facing::Left), Vec2::Y);
+        assert!(transform.translation.x < 0.);
+        assert!(transform.translation.y > 0.);
+    }
+}
@@ -0,0 +1,308 @@
+// 相机
+// CameraRig 跟随玩家: 死区内不移动, 上升时向上预留更多视野, 阻尼平滑并限制在当前关卡内
+// 受击和爆炸时增加 trauma 产生屏幕震动, 切换关卡时缩放过渡
+
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::boss::BossDefeatedEvent;
+use crate::enemy::EnemyKilledEvent;
+use crate::player::{Player, PlayerDamageEvent};
+use crate::settings::Settings;
+
+pub fn camera_plugin(app: &mut App) {
+    app
+        .add_event::<CameraShakeEvent>()
+        .add_systems(
+            PostUpdate,
+            (
+                shake_on_impact,
+                update_camera_bounds,
+                follow_player,
+                apply_camera_rig,
+            )
+                .chain()
+                .after(PhysicsSet::Writeback)
+                .before(TransformSystem::TransformPropagate),
+        );
+}
+
+pub const ASPECT_RATIO: f32 = 16. / 9.;
+
+// 各种冲击产生的 trauma
+const DAMAGE_TRAUMA: f32 = 0.35;
+const KILL_TRAUMA: f32 = 0.15;
+const BOSS_DEFEATED_TRAUMA: f32 = 0.8;
+// 切换关卡时先拉远到该倍数再恢复
+const LEVEL_ZOOM: f32 = 1.15;
+
+// 增加屏幕震动, trauma 取 0~1, 多次叠加
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CameraShakeEvent {
+    pub trauma: f32,
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct CameraRig {
+    // 阻尼系数, 越大跟随越快
+    pub smoothing: f32,
+    // 玩家离开死区 (半宽, 半高) 后相机才跟随
+    pub deadzone: Vec2,
+    // 上升时向上预留的视野, 下落时向下预留的视野
+    pub look_up: f32,
+    pub look_down: f32,
+    // 静止时整体向上的偏移
+    pub look_bias: f32,
+    pub look_smoothing: f32,
+    // 屏幕震动
+    pub trauma: f32,
+    pub trauma_decay: f32,
+    pub max_shake_offset: Vec2,
+    pub max_shake_angle: f32,
+    // 当前缩放和缩放恢复速度
+    pub zoom: f32,
+    pub zoom_smoothing: f32,
+    // 死区跟踪的焦点
+    focus: Option<Vec2>,
+    look: f32,
+    // 平滑后的相机中心和视野大小, 不含震动
+    position: Vec2,
+    size: Vec2,
+    // 当前关卡范围和适配该关卡的视野大小
+    bounds: Option<Rect>,
+    view: Vec2,
+    level: Option<LevelIid>,
+    shake_time: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            smoothing: 6.,
+            deadzone: Vec2::new(16., 24.),
+            look_up: 48.,
+            look_down: 16.,
+            look_bias: 16.,
+            look_smoothing: 3.,
+            trauma: 0.,
+            trauma_decay: 1.2,
+            max_shake_offset: Vec2::new(8., 6.),
+            max_shake_angle: 0.03,
+            zoom: 1.,
+            zoom_smoothing: 3.,
+            focus: None,
+            look: 0.,
+            position: Vec2::ZERO,
+            size: Vec2::ZERO,
+            bounds: None,
+            view: Vec2::ZERO,
+            level: None,
+            shake_time: 0.,
+        }
+    }
+}
+
+impl CameraRig {
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).clamp(0., 1.);
+    }
+
+    // 适配当前关卡的视野大小, 关卡加载前为 None
+    pub fn view(&self) -> Option<Vec2> {
+        (self.view != Vec2::ZERO).then_some(self.view)
+    }
+
+    // 包含缩放过渡的当前视野大小
+    pub fn size(&self) -> Option<Vec2> {
+        (self.size != Vec2::ZERO).then_some(self.size)
+    }
+
+    pub fn level(&self) -> Option<&LevelIid> {
+        self.level.as_ref()
+    }
+
+    // 当前关卡的世界范围
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    // 点移出死区时把焦点拉到死区边缘
+    fn track(&mut self, target: Vec2) -> Vec2 {
+        let deadzone = self.deadzone;
+        let focus = self.focus.get_or_insert(target);
+        let offset = target - *focus;
+        *focus += offset - offset.clamp(-deadzone, deadzone);
+        *focus
+    }
+}
+
+// 按固定宽高比适配关卡, 宽关卡以高度为准, 高关卡以宽度为准
+fn fit_view(level_size: Vec2) -> Vec2 {
+    if level_size.x / level_size.y > ASPECT_RATIO {
+        let height = (level_size.y / 9.).round() * 9.;
+        Vec2::new(height * ASPECT_RATIO, height)
+    } else {
+        let width = (level_size.x / 16.).round() * 16.;
+        Vec2::new(width, width / ASPECT_RATIO)
+    }
+}
+
+// 把视野中心限制在关卡内, 视野大于关卡时居中
+fn clamp_to_bounds(center: Vec2, size: Vec2, bounds: Rect) -> Vec2 {
+    let half = size / 2.;
+    let clamp_axis = |value: f32, min: f32, max: f32, half: f32| {
+        if max - min <= half * 2. {
+            (min + max) / 2.
+        } else {
+            value.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        clamp_axis(center.x, bounds.min.x, bounds.max.x, half.x),
+        clamp_axis(center.y, bounds.min.y, bounds.max.y, half.y),
+    )
+}
+
+// 简单的平滑噪声, 不同 seed 得到不相关的曲线
+fn shake_noise(t: f32, seed: f32) -> f32 {
+    ((t * 17. + seed).sin() + (t * 31. + seed * 1.7).sin() * 0.5) / 1.5
+}
+
+fn shake_on_impact(
+    mut shake_events: EventReader<CameraShakeEvent>,
+    mut damage_events: EventReader<PlayerDamageEvent>,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    mut boss_events: EventReader<BossDefeatedEvent>,
+    mut query: Query<&mut CameraRig>,
+) {
+    let trauma = shake_events.read().map(|e| e.trauma).sum::<f32>()
+        + damage_events.read().count() as f32 * DAMAGE_TRAUMA
+        + killed_events.read().count() as f32 * KILL_TRAUMA
+        + boss_events.read().count() as f32 * BOSS_DEFEATED_TRAUMA;
+    if trauma <= 0. {
+        return;
+    }
+    for mut rig in query.iter_mut() {
+        rig.add_trauma(trauma);
+    }
+}
+
+fn update_camera_bounds(
+    mut query: Query<&mut CameraRig>,
+    level_query: Query<(&Transform, &LevelIid), Without<CameraRig>>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    level_selection: Res<LevelSelection>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    settings: Res<Settings>,
+) {
+    let Some(ldtk_project) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+    else {
+        return;
+    };
+    for (level_transform, level_iid) in &level_query {
+        let Some(level) = ldtk_project.get_raw_level_by_iid(&level_iid.to_string()) else {
+            continue;
+        };
+        if !level_selection.is_match(&LevelIndices::default(), level) {
+            continue;
+        }
+        let level_size = Vec2::new(level.px_wid as f32, level.px_hei as f32);
+        let min = level_transform.translation.truncate();
+        for mut rig in query.iter_mut() {
+            if rig.level.as_ref() == Some(level_iid) {
+                continue;
+            }
+            // 第一次进入关卡直接使用目标大小, 之后切换关卡时拉远再恢复
+            if rig.level.is_some() && !settings.reduce_motion {
+                rig.zoom = LEVEL_ZOOM;
+            }
+            rig.level = Some(level_iid.clone());
+            rig.bounds = Some(Rect::from_corners(min, min + level_size));
+            rig.view = fit_view(level_size);
+        }
+    }
+}
+
+fn follow_player(
+    time: Res<Time>,
+    player_query: Query<(&Transform, Option<&KinematicCharacterControllerOutput>), With<Player>>,
+    mut query: Query<&mut CameraRig>,
+) {
+    let Ok((player_transform, output)) = player_query.get_single() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+    let (movement, grounded) = output
+        .map(|o| (o.effective_translation.y, o.grounded))
+        .unwrap_or((0., true));
+    for mut rig in query.iter_mut() {
+        let Some(bounds) = rig.bounds else {
+            continue;
+        };
+        let first = rig.focus.is_none();
+        let focus = rig.track(player_transform.translation.truncate());
+
+        // 上升时看得更高, 下落时略微向下
+        let look_target = if grounded {
+            rig.look_bias
+        } else if movement > 0. {
+            rig.look_up
+        } else if movement < 0. {
+            -rig.look_down
+        } else {
+            rig.look_bias
+        };
+        let look_blend = 1. - (-rig.look_smoothing * dt).exp();
+        rig.look += (look_target - rig.look) * look_blend;
+
+        rig.zoom += (1. - rig.zoom) * (1. - (-rig.zoom_smoothing * dt).exp());
+        let size_target = rig.view * rig.zoom;
+        let target = clamp_to_bounds(focus + Vec2::Y * rig.look, size_target, bounds);
+
+        if first || rig.size == Vec2::ZERO {
+            rig.position = target;
+            rig.size = size_target;
+        } else {
+            let blend = 1. - (-rig.smoothing * dt).exp();
+            rig.position = rig.position.lerp(target, blend);
+            rig.size = rig.size.lerp(size_target, blend);
+        }
+    }
+}
+
+pub fn apply_camera_rig(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut query: Query<(&mut CameraRig, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let dt = time.delta_seconds();
+    for (mut rig, mut transform, mut projection) in query.iter_mut() {
+        if rig.size == Vec2::ZERO {
+            continue;
+        }
+        rig.trauma = (rig.trauma - rig.trauma_decay * dt).max(0.);
+        rig.shake_time += dt;
+
+        // 震动强度为 trauma 的平方, 小冲击几乎察觉不到
+        let shake = rig.trauma * rig.trauma * settings.shake_scale();
+        let t = rig.shake_time;
+        let offset = rig.max_shake_offset * shake * Vec2::new(shake_noise(t, 0.), shake_noise(t, 7.));
+        let angle = rig.max_shake_angle * shake * shake_noise(t, 13.);
+
+        let position = rig.position + offset;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        transform.rotation = Quat::from_rotation_z(angle);
+        projection.viewport_origin = Vec2::splat(0.5);
+        projection.scaling_mode = ScalingMode::Fixed {
+            width: rig.size.x,
+            height: rig.size.y,
+        };
+    }
+}
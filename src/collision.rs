@@ -0,0 +1,485 @@
+// 碰撞分组和碰撞事件路由
+// 所有碰撞体都通过 Layer 设置 CollisionGroups 和 SolverGroups, 新增实体时在这里补充它会碰到什么
+// route_collisions 每帧把 Rapier 的 CollisionEvent 按标记组件分类一次, 其他系统只读取分类后的事件
+
+use std::ops::BitOr;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::bullet::{AtkEnemy, AtkNormal};
+use crate::enemy::Enemy;
+use crate::pickup::Pickup;
+use crate::player::{Climber, Player};
+use crate::scene::{Climbable, ColorItem, Door, GroundSensor};
+
+pub fn collision_plugin(app: &mut App) {
+    app
+        .add_event::<PlayerEnteredDoor>()
+        .add_event::<PlayerLeftDoor>()
+        .add_event::<BulletHitColorItem>()
+        .add_event::<BulletHitEnemy>()
+        .add_event::<EnemyBulletHit>()
+        .add_event::<ClimberTouchedLadder>()
+        .add_event::<ClimberLeftLadder>()
+        .add_event::<PlayerTouchedPickup>()
+        .add_event::<GroundTouched>()
+        .add_event::<GroundLeft>()
+        // 在 PreUpdate 中分类, Update 中的系统读到的都是本帧已分类好的事件
+        .add_systems(PreUpdate, route_collisions.in_set(CollisionRouteSet));
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CollisionRouteSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Player,
+    Enemy,
+    PlayerBullet,
+    EnemyBullet,
+    // 地形和其他实心物体
+    Wall,
+    // 梯子, 门, 颜色道具等触发区域
+    Sensor,
+    Pickup,
+}
+
+impl Layer {
+    pub fn group(self) -> Group {
+        match self {
+            Layer::Player => Group::GROUP_1,
+            Layer::Enemy => Group::GROUP_2,
+            Layer::PlayerBullet => Group::GROUP_3,
+            Layer::EnemyBullet => Group::GROUP_4,
+            Layer::Wall => Group::GROUP_5,
+            Layer::Sensor => Group::GROUP_6,
+            Layer::Pickup => Group::GROUP_7,
+        }
+    }
+
+    // 会产生碰撞事件的层, 双方都包含对方时才会检测
+    // 子弹不包含发射方, 触发区域只检测玩家和玩家子弹
+    pub fn filters(self) -> Group {
+        match self {
+            Layer::Player => {
+                Layer::Enemy.group() | Layer::EnemyBullet.group() | Layer::Wall.group() | Layer::Sensor.group() | Layer::Pickup.group()
+            }
+            Layer::Enemy => Layer::Player.group() | Layer::PlayerBullet.group() | Layer::Wall.group(),
+            Layer::PlayerBullet => Layer::Enemy.group() | Layer::Wall.group() | Layer::Sensor.group(),
+            Layer::EnemyBullet => Layer::Player.group() | Layer::Wall.group(),
+            Layer::Wall => Group::ALL,
+            Layer::Sensor => Layer::Player.group() | Layer::PlayerBullet.group(),
+            Layer::Pickup => Layer::Player.group(),
+        }
+    }
+
+    // 会产生接触力的层, 子弹只被墙挡住, 不会推动敌人
+    // 触发区域只有门上锁变成实心时才需要挡住玩家
+    pub fn solver_filters(self) -> Group {
+        match self {
+            Layer::Player => Layer::Enemy.group() | Layer::Wall.group() | Layer::Sensor.group(),
+            Layer::Enemy => Layer::Player.group() | Layer::Wall.group(),
+            Layer::PlayerBullet | Layer::EnemyBullet => Layer::Wall.group(),
+            Layer::Wall => Group::ALL,
+            Layer::Sensor => Layer::Player.group(),
+            Layer::Pickup => Group::NONE,
+        }
+    }
+
+    pub fn collision_groups(self) -> CollisionGroups {
+        CollisionGroups::new(self.group(), self.filters())
+    }
+
+    pub fn solver_groups(self) -> SolverGroups {
+        SolverGroups::new(self.group(), self.solver_filters())
+    }
+
+    pub fn groups(self) -> (CollisionGroups, SolverGroups) {
+        (self.collision_groups(), self.solver_groups())
+    }
+}
+
+// 地面检测只关心墙
+pub fn ground_sensor_groups() -> (CollisionGroups, SolverGroups) {
+    (
+        CollisionGroups::new(Layer::Sensor.group(), Layer::Wall.group()),
+        SolverGroups::new(Layer::Sensor.group(), Group::NONE),
+    )
+}
+
+// 实体在碰撞中可能扮演的角色, 由标记组件决定, 一个实体可以同时有多个角色
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Roles(u16);
+
+impl Roles {
+    pub const NONE: Roles = Roles(0);
+    pub const PLAYER: Roles = Roles(1 << 0);
+    pub const CLIMBER: Roles = Roles(1 << 1);
+    pub const ENEMY: Roles = Roles(1 << 2);
+    pub const PLAYER_BULLET: Roles = Roles(1 << 3);
+    pub const ENEMY_BULLET: Roles = Roles(1 << 4);
+    pub const DOOR: Roles = Roles(1 << 5);
+    pub const COLOR_ITEM: Roles = Roles(1 << 6);
+    pub const LADDER: Roles = Roles(1 << 7);
+    pub const PICKUP: Roles = Roles(1 << 8);
+    pub const GROUND_SENSOR: Roles = Roles(1 << 9);
+    // 能站在上面的实心碰撞体, 不包括触发区域和子弹
+    pub const SOLID: Roles = Roles(1 << 10);
+
+    pub fn contains(self, other: Roles) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Roles) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Roles {
+    type Output = Roles;
+
+    fn bitor(self, rhs: Roles) -> Roles {
+        Roles(self.0 | rhs.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Contact {
+    PlayerDoor,
+    BulletColorItem,
+    BulletEnemy,
+    // 敌人子弹碰到除敌人以外的任何东西
+    EnemyBullet,
+    ClimberLadder,
+    PlayerPickup,
+    Ground,
+}
+
+// (类型, 主动方需要的角色, 另一方需要的角色, 另一方不能有的角色)
+const CONTACT_RULES: &[(Contact, Roles, Roles, Roles)] = &[
+    (Contact::PlayerDoor, Roles::PLAYER, Roles::DOOR, Roles::NONE),
+    (Contact::BulletColorItem, Roles::PLAYER_BULLET, Roles::COLOR_ITEM, Roles::NONE),
+    (Contact::BulletEnemy, Roles::PLAYER_BULLET, Roles::ENEMY, Roles::NONE),
+    (Contact::EnemyBullet, Roles::ENEMY_BULLET, Roles::NONE, Roles::ENEMY),
+    (Contact::ClimberLadder, Roles::CLIMBER, Roles::LADDER, Roles::NONE),
+    (Contact::PlayerPickup, Roles::PLAYER, Roles::PICKUP, Roles::NONE),
+    (Contact::Ground, Roles::GROUND_SENSOR, Roles::SOLID, Roles::NONE),
+];
+
+// 按 CONTACT_RULES 的顺序返回这一对实体匹配到的类型
+// bool 为 true 表示主动方是 b, 需要交换两个实体
+pub fn classify(a: Roles, b: Roles) -> Vec<(Contact, bool)> {
+    let matches = |first: Roles, second: Roles, rule: &(Contact, Roles, Roles, Roles)| {
+        first.contains(rule.1) && second.contains(rule.2) && !second.intersects(rule.3)
+    };
+    CONTACT_RULES
+        .iter()
+        .filter_map(|rule| {
+            if matches(a, b, rule) {
+                Some((rule.0, false))
+            } else if matches(b, a, rule) {
+                Some((rule.0, true))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerEnteredDoor {
+    pub player: Entity,
+    pub door: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerLeftDoor {
+    pub player: Entity,
+    pub door: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulletHitColorItem {
+    pub bullet: Entity,
+    pub item: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulletHitEnemy {
+    pub bullet: Entity,
+    pub enemy: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnemyBulletHit {
+    pub bullet: Entity,
+    pub other: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClimberTouchedLadder {
+    pub climber: Entity,
+    pub ladder: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClimberLeftLadder {
+    pub climber: Entity,
+    pub ladder: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerTouchedPickup {
+    pub player: Entity,
+    pub pickup: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroundTouched {
+    pub sensor: Entity,
+    pub ground: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroundLeft {
+    pub sensor: Entity,
+    pub ground: Entity,
+}
+
+#[derive(SystemParam)]
+pub struct CollisionWriters<'w> {
+    entered_door: EventWriter<'w, PlayerEnteredDoor>,
+    left_door: EventWriter<'w, PlayerLeftDoor>,
+    hit_color_item: EventWriter<'w, BulletHitColorItem>,
+    hit_enemy: EventWriter<'w, BulletHitEnemy>,
+    enemy_bullet_hit: EventWriter<'w, EnemyBulletHit>,
+    touched_ladder: EventWriter<'w, ClimberTouchedLadder>,
+    left_ladder: EventWriter<'w, ClimberLeftLadder>,
+    touched_pickup: EventWriter<'w, PlayerTouchedPickup>,
+    touched_ground: EventWriter<'w, GroundTouched>,
+    left_ground: EventWriter<'w, GroundLeft>,
+}
+
+impl CollisionWriters<'_> {
+    // 子弹和拾取只关心开始接触, 其余类型同时有进入和离开
+    fn send(&mut self, contact: Contact, started: bool, first: Entity, second: Entity) {
+        match (contact, started) {
+            (Contact::PlayerDoor, true) => {
+                self.entered_door.send(PlayerEnteredDoor { player: first, door: second });
+            }
+            (Contact::PlayerDoor, false) => {
+                self.left_door.send(PlayerLeftDoor { player: first, door: second });
+            }
+            (Contact::BulletColorItem, true) => {
+                self.hit_color_item.send(BulletHitColorItem { bullet: first, item: second });
+            }
+            (Contact::BulletEnemy, true) => {
+                self.hit_enemy.send(BulletHitEnemy { bullet: first, enemy: second });
+            }
+            (Contact::EnemyBullet, true) => {
+                self.enemy_bullet_hit.send(EnemyBulletHit { bullet: first, other: second });
+            }
+            (Contact::ClimberLadder, true) => {
+                self.touched_ladder.send(ClimberTouchedLadder { climber: first, ladder: second });
+            }
+            (Contact::ClimberLadder, false) => {
+                self.left_ladder.send(ClimberLeftLadder { climber: first, ladder: second });
+            }
+            (Contact::PlayerPickup, true) => {
+                self.touched_pickup.send(PlayerTouchedPickup { player: first, pickup: second });
+            }
+            (Contact::Ground, true) => {
+                self.touched_ground.send(GroundTouched { sensor: first, ground: second });
+            }
+            (Contact::Ground, false) => {
+                self.left_ground.send(GroundLeft { sensor: first, ground: second });
+            }
+            _ => {}
+        }
+    }
+}
+
+type RoleQuery<'w, 's> = Query<'w, 's, (
+    Has<Player>,
+    Has<Climber>,
+    Has<Enemy>,
+    Has<AtkNormal>,
+    Has<AtkEnemy>,
+    Has<Door>,
+    Has<ColorItem>,
+    Has<Climbable>,
+    Has<Pickup>,
+    Has<GroundSensor>,
+    Has<Sensor>,
+)>;
+
+fn roles_of(query: &RoleQuery, entity: Entity) -> Roles {
+    let Ok((player, climber, enemy, player_bullet, enemy_bullet, door, color_item, ladder, pickup, ground_sensor, sensor)) =
+        query.get(entity)
+    else {
+        return Roles::NONE;
+    };
+    let flags = [
+        (player, Roles::PLAYER),
+        (climber, Roles::CLIMBER),
+        (enemy, Roles::ENEMY),
+        (player_bullet, Roles::PLAYER_BULLET),
+        (enemy_bullet, Roles::ENEMY_BULLET),
+        (door, Roles::DOOR),
+        (color_item, Roles::COLOR_ITEM),
+        (ladder, Roles::LADDER),
+        (pickup, Roles::PICKUP),
+        (ground_sensor, Roles::GROUND_SENSOR),
+        (!sensor && !player_bullet && !enemy_bullet, Roles::SOLID),
+    ];
+    flags
+        .into_iter()
+        .filter(|(has, _)| *has)
+        .fold(Roles::NONE, |roles, (_, role)| roles | role)
+}
+
+// 同一帧内按 Rapier 给出的顺序发送, 同一对实体按 CONTACT_RULES 的顺序发送
+fn route_collisions(
+    mut collisions: EventReader<CollisionEvent>,
+    query: RoleQuery,
+    mut writers: CollisionWriters,
+) {
+    for collision_event in collisions.read() {
+        let (e1, e2, started) = match collision_event {
+            CollisionEvent::Started(e1, e2, _) => (*e1, *e2, true),
+            CollisionEvent::Stopped(e1, e2, _) => (*e1, *e2, false),
+        };
+        for (contact, swapped) in classify(roles_of(&query, e1), roles_of(&query, e2)) {
+            let (first, second) = if swapped { (e2, e1) } else { (e1, e2) };
+            writers.send(contact, started, first, second);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashSet;
+    use bevy_rapier2d::rapier::geometry::CollisionEventFlags;
+
+    use super::*;
+
+    // Update 中的系统读到的门事件数量
+    #[derive(Resource, Default)]
+    struct DoorsSeen(usize);
+
+    fn count_doors(mut events: EventReader<PlayerEnteredDoor>, mut seen: ResMut<DoorsSeen>) {
+        seen.0 += events.read().count();
+    }
+
+    fn sent<E: Event + Copy>(app: &App) -> Vec<E> {
+        let events = app.world.resource::<Events<E>>();
+        events.get_reader().read(events).copied().collect()
+    }
+
+    #[test]
+    fn player_door_is_ordered_either_way() {
+        assert_eq!(classify(Roles::PLAYER, Roles::DOOR), vec![(Contact::PlayerDoor, false)]);
+        assert_eq!(classify(Roles::DOOR, Roles::PLAYER), vec![(Contact::PlayerDoor, true)]);
+    }
+
+    #[test]
+    fn bullet_hits_color_item_and_enemy() {
+        assert_eq!(
+            classify(Roles::COLOR_ITEM, Roles::PLAYER_BULLET),
+            vec![(Contact::BulletColorItem, true)]
+        );
+        assert_eq!(
+            classify(Roles::PLAYER_BULLET, Roles::ENEMY | Roles::SOLID),
+            vec![(Contact::BulletEnemy, false)]
+        );
+    }
+
+    #[test]
+    fn enemy_bullet_ignores_enemies() {
+        assert_eq!(classify(Roles::ENEMY_BULLET, Roles::ENEMY | Roles::SOLID), vec![]);
+        assert_eq!(classify(Roles::SOLID, Roles::ENEMY_BULLET), vec![(Contact::EnemyBullet, true)]);
+        assert_eq!(
+            classify(Roles::ENEMY_BULLET, Roles::PLAYER | Roles::SOLID),
+            vec![(Contact::EnemyBullet, false)]
+        );
+    }
+
+    #[test]
+    fn player_gets_every_matching_contact_in_rule_order() {
+        let player = Roles::PLAYER | Roles::CLIMBER | Roles::GROUND_SENSOR | Roles::SOLID;
+        assert_eq!(classify(player, Roles::LADDER), vec![(Contact::ClimberLadder, false)]);
+        assert_eq!(classify(Roles::PICKUP, player), vec![(Contact::PlayerPickup, true)]);
+        // ground_sensor_groups 只让地面检测碰到墙
+        let wall = Roles::SOLID;
+        assert_eq!(classify(player, wall), vec![(Contact::Ground, false)]);
+    }
+
+    #[test]
+    fn ground_ignores_sensors() {
+        let sensor = Roles::GROUND_SENSOR;
+        assert_eq!(classify(sensor, Roles::SOLID), vec![(Contact::Ground, false)]);
+        assert_eq!(classify(sensor, Roles::LADDER), vec![]);
+        assert_eq!(classify(sensor, Roles::DOOR), vec![]);
+        assert_eq!(classify(sensor, Roles::NONE), vec![]);
+    }
+
+    #[test]
+    fn route_collisions_sends_each_typed_event_once() {
+        let mut app = App::new();
+        app.add_event::<CollisionEvent>()
+            .init_resource::<DoorsSeen>()
+            .add_plugins(collision_plugin)
+            .add_systems(Update, count_doors);
+
+        let world = &mut app.world;
+        let player = world.spawn((Player, Climber::default())).id();
+        let door = world.spawn((Door::default(), Sensor)).id();
+        let bullet = world.spawn((AtkNormal, Sensor)).id();
+        let enemy = world.spawn(Enemy).id();
+        let wall = world.spawn_empty().id();
+        let sensor = world
+            .spawn((
+                GroundSensor {
+                    ground_detection_entity: player,
+                    intersecting_ground_entities: HashSet::new(),
+                    on_ground: false,
+                },
+                Sensor,
+            ))
+            .id();
+        let ladder = world.spawn((Climbable, Sensor)).id();
+        let pickup = world.spawn((Pickup::default(), Sensor)).id();
+
+        let flags = CollisionEventFlags::empty();
+        // 顺序故意和规则里的主动方相反, 检查交换
+        world.send_event(CollisionEvent::Started(door, player, flags));
+        world.send_event(CollisionEvent::Started(bullet, enemy, flags));
+        world.send_event(CollisionEvent::Started(wall, sensor, flags));
+        world.send_event(CollisionEvent::Stopped(sensor, wall, flags));
+        world.send_event(CollisionEvent::Started(player, ladder, flags));
+        world.send_event(CollisionEvent::Started(pickup, player, flags));
+        app.update();
+
+        assert_eq!(sent::<PlayerEnteredDoor>(&app), vec![PlayerEnteredDoor { player, door }]);
+        assert_eq!(sent::<BulletHitEnemy>(&app), vec![BulletHitEnemy { bullet, enemy }]);
+        assert_eq!(sent::<GroundTouched>(&app), vec![GroundTouched { sensor, ground: wall }]);
+        assert_eq!(sent::<GroundLeft>(&app), vec![GroundLeft { sensor, ground: wall }]);
+        assert_eq!(
+            sent::<ClimberTouchedLadder>(&app),
+            vec![ClimberTouchedLadder { climber: player, ladder }]
+        );
+        assert_eq!(sent::<PlayerTouchedPickup>(&app), vec![PlayerTouchedPickup { player, pickup }]);
+        assert!(sent::<PlayerLeftDoor>(&app).is_empty());
+        assert!(sent::<BulletHitColorItem>(&app).is_empty());
+        assert!(sent::<EnemyBulletHit>(&app).is_empty());
+        assert!(sent::<ClimberLeftLadder>(&app).is_empty());
+        // PreUpdate 已经分类好, 同一帧的 Update 就能读到
+        assert_eq!(app.world.resource::<DoorsSeen>().0, 1);
+
+        // 没有新的碰撞时不会重复发送
+        app.update();
+        assert_eq!(app.world.resource::<DoorsSeen>().0, 1);
+    }
+}
@@ -1,6 +1,103 @@
 use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
 
 
 
 #[derive(Default)]
-pub struct SetUpFlag(pub bool);
\ No newline at end of file
+pub struct SetUpFlag(pub bool);
+
+// 生命值, 玩家和敌人共用
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.max <= 0. {
+            0.
+        } else {
+            self.current / self.max
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.
+    }
+
+    // 返回是否因此死亡
+    pub fn damage(&mut self, amount: f32) -> bool {
+        let was_alive = !self.is_dead();
+        self.current = (self.current - amount).max(0.);
+        was_alive && self.is_dead()
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new(3.)
+    }
+}
+
+impl From<&EntityInstance> for Health {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        entity_instance
+            .get_maybe_float_field("health")
+            .ok()
+            .copied()
+            .flatten()
+            .map(Health::new)
+            .unwrap_or_default()
+    }
+}
+
+// 朝向, 由移动和瞄准更新, 动画, 子弹和 UI 都读取它
+// 更新时用 set_if_neq, 只在方向变化时触发 Changed
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Facing {
+    Left,
+    #[default]
+    Right,
+}
+
+impl Facing {
+    // x 接近 0 时没有明确朝向
+    pub fn from_x(x: f32) -> Option<Self> {
+        if x > FACING_EPSILON {
+            Some(Facing::Right)
+        } else if x < -FACING_EPSILON {
+            Some(Facing::Left)
+        } else {
+            None
+        }
+    }
+
+    pub fn sign(self) -> f32 {
+        match self {
+            Facing::Left => -1.,
+            Facing::Right => 1.,
+        }
+    }
+
+    pub fn is_left(self) -> bool {
+        self == Facing::Left
+    }
+}
+
+const FACING_EPSILON: f32 = 0.01;
+
+// 精灵跟随朝向翻转
+pub fn flip_sprite_by_facing(mut query: Query<(&Facing, &mut Sprite), Changed<Facing>>) {
+    for (facing, mut sprite) in query.iter_mut() {
+        sprite.flip_x = facing.is_left();
+    }
+}
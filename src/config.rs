@@ -1,12 +1,20 @@
 use bevy::prelude::*;
 use bevy_common_assets::csv::{CsvAssetPlugin, LoadedCsv};
+use bevy_common_assets::ron::RonAssetPlugin;
+
+use crate::animate::AnimationSheet;
+use crate::locale::LocaleEntry;
 
 use crate::roulette::RouletteItemInfo;
+use crate::spawner::WaveInfo;
 
 pub fn config_plugin(app: &mut App) {
     app
         .add_plugins((
             CsvAssetPlugin::<RouletteItemInfo>::new(&["config/item.csv"]),
+            CsvAssetPlugin::<WaveInfo>::new(&["config/wave.csv"]),
+            CsvAssetPlugin::<LocaleEntry>::new(&["locale/en.csv", "locale/zh.csv"]),
+            RonAssetPlugin::<AnimationSheet>::new(&["anim.ron"]),
         ))
         .add_systems(Startup, setup)
         ;
@@ -16,7 +24,12 @@ pub fn config_plugin(app: &mut App) {
 #[derive(Resource)]
 pub struct ItemsHandle(Handle<LoadedCsv<RouletteItemInfo>>);
 
+#[derive(Resource)]
+pub struct WavesHandle(pub Handle<LoadedCsv<WaveInfo>>);
+
 pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let items: ItemsHandle = ItemsHandle(asset_server.load("config/item.csv"));
     commands.insert_resource(items);
+    let waves: WavesHandle = WavesHandle(asset_server.load("config/wave.csv"));
+    commands.insert_resource(waves);
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+// 调试控制台, 只在启用 dev feature 时编译
+// ` 键打开, 输入命令后回车执行, 上下键翻看历史; 打开时键盘输入不传给游戏
+// 各插件用 add_console_command 注册自己的命令
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+pub fn console_plugin(app: &mut App) {
+    app
+        .init_resource::<Console>()
+        .add_console_command("help", "help: 列出所有命令", console_help)
+        .add_console_command("clear", "clear: 清空输出", console_clear)
+        .add_console_command("timescale", "timescale <倍数>: 设置游戏速度, 1 为正常", console_timescale)
+        .add_systems(Startup, setup_console)
+        .add_systems(PreUpdate, console_input.after(InputSystem))
+        .add_systems(Update, (
+            run_console_commands,
+            update_console_text,
+        ).chain());
+}
+
+const CONSOLE_FONT: &str = "fonts/FiraSans-Bold.ttf";
+const CONSOLE_FONT_SIZE: f32 = 16.;
+const CONSOLE_TOGGLE_KEY: KeyCode = KeyCode::Backquote;
+// 显示的输出行数, 更早的行丢弃
+const CONSOLE_MAX_LINES: usize = 12;
+
+// 成功时返回要显示的文字, 可以为空
+pub type ConsoleResult = Result<String, String>;
+pub type ConsoleCommandFn = fn(&mut World, &[&str]) -> ConsoleResult;
+
+#[derive(Clone, Copy)]
+pub struct ConsoleCommand {
+    pub help: &'static str,
+    pub run: ConsoleCommandFn,
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+pub trait AddConsoleCommand {
+    fn add_console_command(&mut self, name: &'static str, help: &'static str, run: ConsoleCommandFn) -> &mut Self;
+}
+
+// 与插件的注册顺序无关, 控制台插件之前注册的命令也有效
+impl AddConsoleCommand for App {
+    fn add_console_command(&mut self, name: &'static str, help: &'static str, run: ConsoleCommandFn) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(name, ConsoleCommand { help, run });
+        self
+    }
+}
+
+// 读取第 index 个参数, 缺少或格式错误时返回说明
+pub fn console_arg<T: FromStr>(args: &[&str], index: usize, name: &str) -> Result<T, String> {
+    let arg = args.get(index).ok_or_else(|| format!("missing <{}>", name))?;
+    arg.parse().map_err(|_| format!("invalid <{}>: {}", name, arg))
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    lines: Vec<String>,
+    history: Vec<String>,
+    // 正在查看的历史记录, None 为新输入
+    history_index: Option<usize>,
+    pending: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, text: &str) {
+        self.lines.extend(text.lines().map(str::to_string));
+        let excess = self.lines.len().saturating_sub(CONSOLE_MAX_LINES);
+        self.lines.drain(..excess);
+    }
+
+    fn browse_history(&mut self, step: i32) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() as i32 - 1;
+        let index = match self.history_index {
+            Some(index) => index as i32 + step,
+            None if step < 0 => last,
+            None => return,
+        };
+        if index > last {
+            self.history_index = None;
+            self.input.clear();
+        } else {
+            let index = index.max(0) as usize;
+            self.history_index = Some(index);
+            self.input = self.history[index].clone();
+        }
+    }
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn setup_console(mut cmds: Commands, asset_server: Res<AssetServer>) {
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.),
+                left: Val::Px(0.),
+                width: Val::Percent(100.),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.8).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(200),
+            ..default()
+        },
+        ConsoleRoot,
+        Name::new("console"),
+    )).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(CONSOLE_FONT),
+                    font_size: CONSOLE_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            ),
+            ConsoleText,
+        ));
+    });
+}
+
+// 在 InputSystem 之后读取键盘, 控制台打开时清空按键状态, 后面的系统收不到输入
+fn console_input(
+    mut console: ResMut<Console>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut char_events: EventReader<ReceivedCharacter>,
+) {
+    if keys.just_pressed(CONSOLE_TOGGLE_KEY) {
+        console.open = !console.open;
+        keys.reset_all();
+        char_events.clear();
+        return;
+    }
+    if !console.open {
+        char_events.clear();
+        return;
+    }
+
+    for event in char_events.read() {
+        for c in event.char.chars().filter(|c| !c.is_control() && *c != '`') {
+            console.input.push(c);
+        }
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        console.input.pop();
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        console.browse_history(-1);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        console.browse_history(1);
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        let line = std::mem::take(&mut console.input).trim().to_string();
+        console.history_index = None;
+        if !line.is_empty() {
+            if console.history.last() != Some(&line) {
+                console.history.push(line.clone());
+            }
+            console.pending.push(line);
+        }
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    }
+    keys.reset_all();
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    for line in pending {
+        world.resource_mut::<Console>().print(&format!("> {}", line));
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        let _span = info_span!("console_command", command = name).entered();
+        debug!("run {:?}", args);
+        let command = world.resource::<ConsoleCommands>().0.get(name).copied();
+        let output = match command {
+            Some(command) => match (command.run)(world, &args) {
+                Ok(text) => text,
+                Err(e) => format!("error: {}\nusage: {}", e, command.help),
+            },
+            None => format!("unknown command '{}', type help for a list", name),
+        };
+        world.resource_mut::<Console>().print(&output);
+    }
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    mut query_root: Query<&mut Visibility, With<ConsoleRoot>>,
+    mut query_text: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut visibility in query_root.iter_mut() {
+        *visibility = if console.open { Visibility::Visible } else { Visibility::Hidden };
+    }
+    let mut value = console.lines.join("\n");
+    if !value.is_empty() {
+        value.push('\n');
+    }
+    value.push_str(&format!("> {}_", console.input));
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+fn console_help(world: &mut World, _args: &[&str]) -> ConsoleResult {
+    Ok(world
+        .resource::<ConsoleCommands>()
+        .0
+        .values()
+        .map(|command| command.help)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn console_clear(world: &mut World, _args: &[&str]) -> ConsoleResult {
+    world.resource_mut::<Console>().lines.clear();
+    Ok(String::new())
+}
+
+fn console_timescale(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let scale: f32 = console_arg(args, 0, "scale")?;
+    if scale <= 0. || !scale.is_finite() {
+        return Err("scale must be positive".to_string());
+    }
+    world.resource_mut::<Time<Virtual>>().set_relative_speed(scale);
+    Ok(format!("timescale {}", scale))
+}
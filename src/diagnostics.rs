@@ -0,0 +1,198 @@
+// 调试信息面板, 需要启用 diagnostics feature
+// F3 切换, 显示帧率, 实体和碰撞体数量, 当前关卡, 轮盘状态和玩家控制器状态
+
+use bevy::diagnostic::{DiagnosticPath, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::player::{Climber, Invulnerable, Noclip, Player};
+use crate::roulette::Roulette;
+use crate::scene::GroundSensor;
+
+pub fn diagnostics_plugin(app: &mut App) {
+    app
+        .add_plugins((
+            FrameTimeDiagnosticsPlugin,
+            EntityCountDiagnosticsPlugin,
+        ))
+        .init_resource::<DiagnosticsOverlay>()
+        .add_systems(Startup, setup_overlay)
+        .add_systems(Update, (
+            toggle_overlay,
+            update_overlay,
+        ).chain());
+}
+
+const OVERLAY_FONT: &str = "fonts/FiraSans-Bold.ttf";
+const OVERLAY_FONT_SIZE: f32 = 14.;
+const OVERLAY_TOGGLE_KEY: KeyCode = KeyCode::F3;
+// 刷新间隔, 秒
+const OVERLAY_REFRESH: f32 = 0.25;
+
+#[derive(Resource)]
+struct DiagnosticsOverlay {
+    visible: bool,
+    timer: Timer,
+}
+
+impl Default for DiagnosticsOverlay {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            timer: Timer::from_seconds(OVERLAY_REFRESH, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Component)]
+struct OverlayText;
+
+fn setup_overlay(mut cmds: Commands, asset_server: Res<AssetServer>) {
+    cmds.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(OVERLAY_FONT),
+                    font_size: OVERLAY_FONT_SIZE,
+                    color: Color::YELLOW,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(8.),
+                left: Val::Px(8.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(150),
+            ..default()
+        },
+        OverlayText,
+        Name::new("diagnostics_overlay"),
+    ));
+}
+
+fn toggle_overlay(
+    input: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<DiagnosticsOverlay>,
+    mut query: Query<&mut Visibility, With<OverlayText>>,
+) {
+    if !input.just_pressed(OVERLAY_TOGGLE_KEY) {
+        return;
+    }
+    overlay.visible = !overlay.visible;
+    // 打开时立即刷新
+    let duration = overlay.timer.duration();
+    overlay.timer.set_elapsed(duration);
+    for mut visibility in query.iter_mut() {
+        *visibility = if overlay.visible { Visibility::Visible } else { Visibility::Hidden };
+    }
+}
+
+fn update_overlay(
+    time: Res<Time<Real>>,
+    mut overlay: ResMut<DiagnosticsOverlay>,
+    diagnostics: Res<DiagnosticsStore>,
+    level_selection: Option<Res<LevelSelection>>,
+    query_colliders: Query<(Option<&Sensor>, Has<ColliderDisabled>), With<Collider>>,
+    query_roulette: Query<&Roulette>,
+    query_player: Query<
+        (
+            &Transform,
+            Option<&KinematicCharacterControllerOutput>,
+            Option<&GroundSensor>,
+            Option<&Climber>,
+            Has<Noclip>,
+            Has<Invulnerable>,
+        ),
+        With<Player>,
+    >,
+    mut query_text: Query<&mut Text, With<OverlayText>>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    overlay.timer.tick(time.delta());
+    if !overlay.timer.just_finished() {
+        return;
+    }
+
+    let smoothed = |path: &DiagnosticPath| diagnostics.get(path).and_then(|d| d.smoothed()).unwrap_or_default();
+    let mut lines = vec![
+        format!(
+            "FPS {:.0} ({:.1} ms)",
+            smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+            smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        ),
+        format!("entities {:.0}", smoothed(&EntityCountDiagnosticsPlugin::ENTITY_COUNT)),
+    ];
+
+    let (mut colliders, mut sensors, mut disabled) = (0, 0, 0);
+    for (sensor, is_disabled) in query_colliders.iter() {
+        colliders += 1;
+        sensors += sensor.is_some() as usize;
+        disabled += is_disabled as usize;
+    }
+    lines.push(format!("colliders {} (sensors {}, disabled {})", colliders, sensors, disabled));
+
+    let level = match level_selection.as_deref() {
+        Some(LevelSelection::Iid(iid)) => iid.to_string(),
+        Some(other) => format!("{:?}", other),
+        None => "-".to_string(),
+    };
+    lines.push(format!("level {}", level));
+
+    for roulette in query_roulette.iter() {
+        let current = roulette
+            .get_cur_item()
+            .map(|slot| format!("id {} x{} ({}/{})", slot.id, slot.count, slot.charges, slot.max_charges))
+            .unwrap_or_else(|| "-".to_string());
+        lines.push(format!(
+            "roulette {}/{} index {} current {}{}{}",
+            roulette.len(),
+            roulette.capacity(),
+            roulette.get_cur_index(),
+            current,
+            if roulette.is_spinning() { " spinning" } else { "" },
+            roulette.get_pending().map(|slot| format!(" pending {}", slot.id)).unwrap_or_default(),
+        ));
+    }
+
+    for (transform, output, ground, climber, noclip, invulnerable) in query_player.iter() {
+        lines.push(format!(
+            "player {:.0} {:.0}",
+            transform.translation.x, transform.translation.y,
+        ));
+        if let Some(output) = output {
+            lines.push(format!(
+                "controller grounded {} moved {:.1} {:.1} collisions {}",
+                output.grounded,
+                output.effective_translation.x,
+                output.effective_translation.y,
+                output.collisions.len(),
+            ));
+        }
+        let mut flags = vec![];
+        if ground.is_some_and(|g| g.on_ground) {
+            flags.push("on_ground");
+        }
+        if climber.is_some_and(|c| c.climbing) {
+            flags.push("climbing");
+        }
+        if noclip {
+            flags.push("noclip");
+        }
+        if invulnerable {
+            flags.push("god");
+        }
+        lines.push(format!("state {}", if flags.is_empty() { "-".to_string() } else { flags.join(" ") }));
+    }
+
+    let value = lines.join("\n");
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,273 @@
+// 显示设置
+// 分辨率, 全屏和垂直同步来自 Settings, 游戏画面保持 16:9, 其余区域用黑边填充
+// 像素完美模式下画面按整数倍缩放, 相机位置对齐到屏幕像素, LDtk 图块使用最近邻采样
+
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::render::texture::ImageSampler;
+use bevy::render::view::RenderLayers;
+use bevy::transform::TransformSystem;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode};
+
+use crate::camera::{apply_camera_rig, CameraRig, ASPECT_RATIO};
+use crate::settings::Settings;
+
+pub fn display_plugin(app: &mut App) {
+    app
+        .add_systems(Startup, setup_letterbox)
+        .add_systems(Update, (
+            apply_window_settings,
+            use_nearest_for_tilesets,
+        ).chain())
+        .add_systems(
+            PostUpdate,
+            (update_viewport, update_letterbox)
+                .chain()
+                .after(apply_camera_rig)
+                .before(TransformSystem::TransformPropagate),
+        );
+
+    #[cfg(feature = "dev")]
+    {
+        app.add_systems(Update, test_display.before(apply_window_settings));
+    }
+}
+
+// 可选的窗口分辨率
+pub const RESOLUTIONS: [[u32; 2]; 5] = [
+    [960, 540],
+    [1280, 720],
+    [1600, 900],
+    [1920, 1080],
+    [2560, 1440],
+];
+pub const DEFAULT_RESOLUTION: [u32; 2] = [1280, 720];
+
+// 黑边只在这一层绘制, 不会出现在游戏相机中
+const LETTERBOX_LAYER: u8 = 31;
+// LDtk 图块所在目录
+const TILESET_DIR: &str = "ldtk/";
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum LetterboxBar {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+// 在窗口中居中的最大画面, 像素完美时取不超过窗口的最大整数倍
+// view 为画面对应的世界大小, 窗口比 view 还小时退回到普通缩放
+pub fn viewport_size(window: UVec2, view: Vec2, pixel_perfect: bool) -> UVec2 {
+    let fit = (window.as_vec2() / view).min_element();
+    let scale = if pixel_perfect && fit >= 1. { fit.floor() } else { fit };
+    (view * scale).round().as_uvec2().clamp(UVec2::ONE, window.max(UVec2::ONE))
+}
+
+// 列表中前后 step 个的分辨率, 循环选择, 不在列表中时回到第一个
+pub fn next_resolution(current: [u32; 2], step: i32) -> [u32; 2] {
+    let len = RESOLUTIONS.len() as i32;
+    RESOLUTIONS
+        .iter()
+        .position(|r| *r == current)
+        .map(|i| RESOLUTIONS[(i as i32 + step).rem_euclid(len) as usize])
+        .unwrap_or(RESOLUTIONS[0])
+}
+
+// 光标对应的世界坐标, 光标在黑边上时为 None
+// 画面通过 camera.viewport 居中, 光标坐标要先减去视口的左上角
+pub fn cursor_to_world(window: &Window, camera: &Camera, camera_transform: &GlobalTransform) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let viewport = camera.logical_viewport_rect()?;
+    if !viewport.contains(cursor) {
+        return None;
+    }
+    camera.viewport_to_world_2d(camera_transform, cursor - viewport.min)
+}
+
+// 黑边在窗口中的区域, 坐标以左上角为原点
+fn bar_rect(bar: LetterboxBar, window: Vec2, viewport: Rect) -> Rect {
+    match bar {
+        LetterboxBar::Left => Rect::new(0., 0., viewport.min.x, window.y),
+        LetterboxBar::Right => Rect::new(viewport.max.x, 0., window.x, window.y),
+        LetterboxBar::Top => Rect::new(viewport.min.x, 0., viewport.max.x, viewport.min.y),
+        LetterboxBar::Bottom => Rect::new(viewport.min.x, viewport.max.y, viewport.max.x, window.y),
+    }
+}
+
+fn setup_letterbox(mut cmds: Commands) {
+    let layer = RenderLayers::layer(LETTERBOX_LAYER);
+    cmds.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                // 在游戏相机之后绘制, 不清除画面
+                order: 1,
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            ..default()
+        },
+        layer,
+        Name::new("letterbox_camera"),
+    ));
+    for bar in [LetterboxBar::Left, LetterboxBar::Right, LetterboxBar::Top, LetterboxBar::Bottom] {
+        cmds.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::BLACK,
+                    custom_size: Some(Vec2::ZERO),
+                    ..default()
+                },
+                ..default()
+            },
+            bar,
+            layer,
+            Name::new(format!("letterbox_{:?}", bar)),
+        ));
+    }
+}
+
+#[cfg(feature = "dev")]
+pub fn test_display(
+    mut settings: ResMut<Settings>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::F10) {
+        settings.resolution = next_resolution(settings.resolution, 1);
+    }
+    if input.just_pressed(KeyCode::F11) {
+        settings.fullscreen = !settings.fullscreen;
+    }
+    if input.just_pressed(KeyCode::F12) {
+        settings.pixel_perfect = !settings.pixel_perfect;
+    }
+}
+
+fn apply_window_settings(
+    settings: Res<Settings>,
+    mut query_window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let Ok(mut window) = query_window.get_single_mut() else {
+        return;
+    };
+    let mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+    if window.mode != mode {
+        window.mode = mode;
+    }
+    let present_mode = if settings.vsync {
+        PresentMode::AutoVsync
+    } else {
+        PresentMode::AutoNoVsync
+    };
+    if window.present_mode != present_mode {
+        window.present_mode = present_mode;
+    }
+    let [width, height] = settings.resolution;
+    if width > 0 && height > 0 && (window.resolution.width() != width as f32 || window.resolution.height() != height as f32) {
+        window.resolution.set(width as f32, height as f32);
+    }
+}
+
+// 只处理 LDtk 图块, 角色等缩放显示的贴图保持线性采样
+fn use_nearest_for_tilesets(
+    mut events: EventReader<AssetEvent<Image>>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for event in events.read() {
+        let AssetEvent::LoadedWithDependencies { id } = event else {
+            continue;
+        };
+        let is_tileset = asset_server
+            .get_path(*id)
+            .is_some_and(|path| path.path().starts_with(TILESET_DIR));
+        if !is_tileset {
+            continue;
+        }
+        if let Some(image) = images.get_mut(*id) {
+            image.sampler = ImageSampler::nearest();
+        }
+    }
+}
+
+fn update_viewport(
+    settings: Res<Settings>,
+    query_window: Query<&Window, With<PrimaryWindow>>,
+    mut query_camera: Query<(&mut Camera, &mut Transform, &CameraRig)>,
+) {
+    let Ok(window) = query_window.get_single() else {
+        return;
+    };
+    let physical = UVec2::new(window.physical_width(), window.physical_height());
+    // 窗口最小化
+    if physical.x == 0 || physical.y == 0 {
+        return;
+    }
+    for (mut camera, mut transform, rig) in query_camera.iter_mut() {
+        let (view, pixel_perfect) = match rig.view() {
+            Some(view) => (view, settings.pixel_perfect),
+            None => (Vec2::new(ASPECT_RATIO, 1.), false),
+        };
+        let size = viewport_size(physical, view, pixel_perfect);
+        let position = (physical - size) / 2;
+        let unchanged = camera
+            .viewport
+            .as_ref()
+            .is_some_and(|v| v.physical_position == position && v.physical_size == size);
+        if !unchanged {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            });
+        }
+
+        // 相机对齐到屏幕像素, 避免图块接缝和抖动
+        if pixel_perfect {
+            if let Some(world) = rig.size() {
+                let scale = size.x as f32 / world.x;
+                transform.translation.x = (transform.translation.x * scale).round() / scale;
+                transform.translation.y = (transform.translation.y * scale).round() / scale;
+            }
+        }
+    }
+}
+
+fn update_letterbox(
+    query_window: Query<&Window, With<PrimaryWindow>>,
+    query_camera: Query<&Camera, With<CameraRig>>,
+    mut query_bar: Query<(&LetterboxBar, &mut Sprite, &mut Transform)>,
+) {
+    let (Ok(window), Ok(camera)) = (query_window.get_single(), query_camera.get_single()) else {
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+    let scale = window.scale_factor();
+    let viewport = match camera.viewport.as_ref() {
+        Some(v) => Rect::from_corners(
+            v.physical_position.as_vec2() / scale,
+            (v.physical_position + v.physical_size).as_vec2() / scale,
+        ),
+        None => Rect::from_corners(Vec2::ZERO, window_size),
+    };
+    for (bar, mut sprite, mut transform) in query_bar.iter_mut() {
+        let rect = bar_rect(*bar, window_size, viewport);
+        let size = rect.size().max(Vec2::ZERO);
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+        // 黑边相机以窗口中心为原点, y 轴向上
+        let center = rect.center();
+        let translation = Vec3::new(center.x - window_size.x / 2., window_size.y / 2. - center.y, 0.);
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+    }
+}
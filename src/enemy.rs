@@ -1,24 +1,438 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_ecs_ldtk::{prelude::*, utils::ldtk_pixel_coords_to_translation_pivoted};
+use serde::{Deserialize, Serialize};
+
+use crate::bullet::spawn_atk_enemy;
+use crate::comm::{Facing, Health};
+use crate::global::Score;
+use crate::player::{Player, PlayerDamageEvent};
 
 
 pub fn enemy_plugin(app: &mut App) {
     app
+        .add_event::<EnemyHitEvent>()
+        .add_event::<EnemyKilledEvent>()
         .add_systems(Update, (
-            patrol,
-        ));
+            enemy_sense,
+            (patrol, enemy_act),
+            on_enemy_hit,
+        ).chain());
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
 pub struct Enemy;
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component, Serialize, Deserialize)]
+pub enum EnemyKind {
+    #[default]
+    Walker,
+    // 飞行, 无视地形直接追向玩家
+    Flyer,
+    // 固定炮台, 看到玩家就发射子弹
+    Turret,
+}
+
+impl EnemyKind {
+    // LDtk 实体名或波次配置中的 kind
+    pub fn from_identifier(identifier: &str) -> Self {
+        match identifier {
+            "Flyer" => EnemyKind::Flyer,
+            "Turret" => EnemyKind::Turret,
+            _ => EnemyKind::Walker,
+        }
+    }
+}
+
+impl From<&EntityInstance> for EnemyKind {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        EnemyKind::from_identifier(&entity_instance.identifier)
+    }
+}
+
+// 敌人属性, 从 LDtk 字段读取, 没有配置时用默认值
+#[derive(Clone, Debug, PartialEq, Component, Serialize, Deserialize)]
+pub struct EnemyStats {
+    pub speed: f32,
+    pub aggro_range: f32,
+    pub attack_range: f32,
+    pub attack_interval: f32,
+    pub damage: f32,
+    // 生命比例低于该值时逃跑
+    pub flee_health: f32,
+}
+
+impl Default for EnemyStats {
+    fn default() -> Self {
+        Self {
+            speed: 75.,
+            aggro_range: 120.,
+            attack_range: 16.,
+            attack_interval: 1.,
+            damage: 1.,
+            flee_health: 0.,
+        }
+    }
+}
+
+impl EnemyStats {
+    pub fn for_kind(kind: EnemyKind) -> Self {
+        match kind {
+            // 炮台的 speed 为子弹速度
+            EnemyKind::Turret => EnemyStats {
+                speed: 150.,
+                aggro_range: 160.,
+                attack_range: 160.,
+                attack_interval: 1.5,
+                ..default()
+            },
+            _ => EnemyStats::default(),
+        }
+    }
+}
+
+impl From<&EntityInstance> for EnemyStats {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let field = |name: &str| {
+            entity_instance
+                .get_maybe_float_field(name)
+                .ok()
+                .copied()
+                .flatten()
+        };
+        let stats = EnemyStats::for_kind(entity_instance.into());
+        EnemyStats {
+            speed: field("speed").unwrap_or(stats.speed),
+            aggro_range: field("aggro_range").unwrap_or(stats.aggro_range),
+            attack_range: field("attack_range").unwrap_or(stats.attack_range),
+            attack_interval: field("attack_interval").unwrap_or(stats.attack_interval),
+            damage: field("damage").unwrap_or(stats.damage),
+            flee_health: field("flee_health").unwrap_or(stats.flee_health),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum EnemyState {
+    #[default]
+    Patrol,
+    Chase,
+    Attack,
+    Flee,
+    Stunned,
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub struct EnemyBrain {
+    pub state: EnemyState,
+    pub attack_timer: Timer,
+    pub stun_timer: Timer,
+}
+
+impl EnemyBrain {
+    pub fn stun(&mut self, seconds: f32) {
+        self.state = EnemyState::Stunned;
+        self.stun_timer = Timer::from_seconds(seconds, TimerMode::Once);
+    }
+}
+
+// 敌人被击中
+#[derive(Event)]
+pub struct EnemyHitEvent {
+    pub enemy: Entity,
+    pub damage: f32,
+}
+
+// 敌人被消灭, 发送时实体已在销毁队列中
+#[derive(Event)]
+pub struct EnemyKilledEvent {
+    pub enemy: Entity,
+    pub position: Vec2,
+}
+
+const ENEMY_STUN_TIME: f32 = 0.4;
+const ENEMY_KILL_SCORE: u32 = 100;
+const ENEMY_BULLET_SIZE: f32 = 6.;
+
+// 水平方向的符号, 玩家几乎在正上方或正下方时为 0, 避免原地左右抖动
+fn walk_dir(x: f32) -> f32 {
+    Facing::from_x(x).map_or(0., Facing::sign)
+}
+
+// 沿射线检测到的第一个碰撞体是玩家时说明能看到玩家
+fn can_see(
+    rapier_context: &RapierContext,
+    from: Vec2,
+    to: Vec2,
+    range: f32,
+    enemy: Entity,
+    player: Entity,
+) -> bool {
+    let delta = to - from;
+    let distance = delta.length();
+    if distance > range {
+        return false;
+    }
+    if distance <= f32::EPSILON {
+        return true;
+    }
+    let filter = QueryFilter::new().exclude_collider(enemy).exclude_sensors();
+    matches!(
+        rapier_context.cast_ray(from, delta / distance, distance, true, filter),
+        Some((hit, _)) if hit == player
+    )
+}
+
+// 根据与玩家的距离, 视线和自身血量切换状态
+pub fn enemy_sense(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    query_player: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut query: Query<(Entity, &GlobalTransform, &EnemyKind, &EnemyStats, &Health, &mut EnemyBrain), With<Enemy>>,
+) {
+    let Ok((player, player_transform)) = query_player.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation().truncate();
+    for (entity, transform, kind, stats, health, mut brain) in query.iter_mut() {
+        brain.attack_timer.tick(time.delta());
+        if brain.state == EnemyState::Stunned {
+            brain.stun_timer.tick(time.delta());
+            if !brain.stun_timer.finished() {
+                continue;
+            }
+        }
+
+        let pos = transform.translation().truncate();
+        let distance = pos.distance(player_pos);
+        let seen = can_see(&rapier_context, pos, player_pos, stats.aggro_range, entity, player);
+
+        brain.state = if *kind != EnemyKind::Turret && health.fraction() < stats.flee_health {
+            if distance < stats.aggro_range * 1.5 {
+                EnemyState::Flee
+            } else {
+                EnemyState::Patrol
+            }
+        } else if seen && (distance <= stats.attack_range || *kind == EnemyKind::Turret) {
+            EnemyState::Attack
+        } else if seen {
+            EnemyState::Chase
+        } else {
+            EnemyState::Patrol
+        };
+    }
+}
+
+// 执行当前状态的行为, 巡逻由 patrol 处理
+pub fn enemy_act(
+    mut cmds: Commands,
+    query_player: Query<(Entity, &GlobalTransform), With<Player>>,
+    mut query: Query<(Entity, &GlobalTransform, &EnemyKind, &EnemyStats, &mut EnemyBrain, &mut Velocity, Option<&mut Facing>), With<Enemy>>,
+    mut damage_event: EventWriter<PlayerDamageEvent>,
+) {
+    let Ok((player, player_transform)) = query_player.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation().truncate();
+    for (entity, transform, kind, stats, mut brain, mut velocity, facing) in query.iter_mut() {
+        let pos = transform.translation().truncate();
+        let dir = (player_pos - pos).normalize_or_zero();
+        // 追击和攻击时面向玩家
+        if let (Some(mut facing), Some(new_facing)) = (facing, Facing::from_x(dir.x)) {
+            if matches!(brain.state, EnemyState::Chase | EnemyState::Attack) {
+                facing.set_if_neq(new_facing);
+            }
+        }
+        let chase = match kind {
+            EnemyKind::Walker => Vec2::new(walk_dir(dir.x) * stats.speed * 1.5, 0.),
+            EnemyKind::Flyer => dir * stats.speed,
+            EnemyKind::Turret => Vec2::ZERO,
+        };
+
+        match brain.state {
+            EnemyState::Patrol => {
+                if *kind != EnemyKind::Walker {
+                    velocity.linvel = Vec2::ZERO;
+                }
+            }
+            EnemyState::Chase => {
+                velocity.linvel = chase;
+            }
+            EnemyState::Attack => {
+                velocity.linvel = if *kind == EnemyKind::Flyer { chase } else { Vec2::ZERO };
+                if !brain.attack_timer.finished() {
+                    continue;
+                }
+                brain.attack_timer = Timer::from_seconds(stats.attack_interval, TimerMode::Once);
+                if *kind == EnemyKind::Turret {
+                    let spawn_at = transform.translation() + (dir * ENEMY_BULLET_SIZE * 2.).extend(0.);
+                    spawn_atk_enemy(
+                        &mut cmds,
+                        &Transform::from_translation(spawn_at),
+                        dir * stats.speed,
+                        Vec2::splat(ENEMY_BULLET_SIZE),
+                        stats.damage,
+                    );
+                } else {
+                    damage_event.send(PlayerDamageEvent {
+                        source: entity,
+                        target: player,
+                        damage: stats.damage,
+                    });
+                }
+            }
+            EnemyState::Flee => {
+                velocity.linvel = match kind {
+                    EnemyKind::Walker => Vec2::new(-walk_dir(dir.x) * stats.speed, 0.),
+                    _ => -dir * stats.speed,
+                };
+            }
+            EnemyState::Stunned => {
+                velocity.linvel = Vec2::ZERO;
+            }
+        }
+    }
+}
+
+pub fn on_enemy_hit(
+    mut cmds: Commands,
+    mut events: EventReader<EnemyHitEvent>,
+    mut query: Query<(&mut Health, &GlobalTransform, Option<&mut EnemyBrain>), With<Enemy>>,
+    mut score: ResMut<Score>,
+    mut killed_event: EventWriter<EnemyKilledEvent>,
+) {
+    for event in events.read() {
+        let Ok((mut health, transform, brain)) = query.get_mut(event.enemy) else {
+            continue;
+        };
+        if health.damage(event.damage) {
+            debug!("enemy {:?} killed", event.enemy);
+            score.0 += ENEMY_KILL_SCORE;
+            killed_event.send(EnemyKilledEvent {
+                enemy: event.enemy,
+                position: transform.translation().truncate(),
+            });
+            cmds.entity(event.enemy).despawn_recursive();
+        } else if let Some(mut brain) = brain {
+            brain.stun(ENEMY_STUN_TIME);
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum PatrolMode {
+    // 走到头再原路返回
+    #[default]
+    PingPong,
+    // 走到最后一个点后回到第一个点
+    Loop,
+}
 
 #[derive(Clone, PartialEq, Debug, Default, Component)]
 pub struct Patrol {
     pub points: Vec<Vec2>,
+    // 到达每个点后停留的秒数, 缺省为 0
+    pub waits: Vec<f32>,
+    // 每一段的速度, 第 i 段为点 i 到下一个点, 缺省使用 EnemyStats::speed
+    pub speeds: Vec<f32>,
+    pub mode: PatrolMode,
+    // 每段起步和到达时减速
+    pub easing: bool,
     pub index: usize,
     pub forward: bool,
+    // 剩余停留时间
+    pub wait: f32,
+}
+
+// 速度缓动的最小比例, 避免在端点停住
+const PATROL_EASE_MIN: f32 = 0.25;
+
+impl Patrol {
+    pub fn new(points: Vec<Vec2>) -> Self {
+        Patrol {
+            points,
+            index: 1,
+            forward: true,
+            ..default()
+        }
+    }
+
+    fn prev_index(&self) -> usize {
+        let len = self.points.len();
+        match self.mode {
+            PatrolMode::Loop => (self.index + len - 1) % len,
+            PatrolMode::PingPong if self.forward => self.index.saturating_sub(1),
+            PatrolMode::PingPong => (self.index + 1).min(len - 1),
+        }
+    }
+
+    fn advance(&mut self) {
+        let len = self.points.len();
+        match self.mode {
+            PatrolMode::Loop => {
+                self.forward = true;
+                self.index = (self.index + 1) % len;
+            }
+            PatrolMode::PingPong => {
+                if self.index == 0 {
+                    self.forward = true;
+                } else if self.index == len - 1 {
+                    self.forward = false;
+                }
+                if self.forward {
+                    self.index += 1;
+                } else {
+                    self.index -= 1;
+                }
+            }
+        }
+    }
+
+    // 当前这一段的速度
+    pub fn segment_speed(&self, default_speed: f32) -> f32 {
+        let prev = self.prev_index();
+        let segment = match self.mode {
+            PatrolMode::Loop => prev,
+            PatrolMode::PingPong => prev.min(self.index),
+        };
+        self.speeds.get(segment).copied().filter(|s| *s > 0.).unwrap_or(default_speed)
+    }
+
+    // 根据当前位置推进巡逻, 返回这一帧的速度
+    // 快到达时把速度缩小到刚好走到目标点, 不会越过也不需要修正位置
+    pub fn step(&mut self, position: Vec2, dt: f32, default_speed: f32) -> Vec2 {
+        if self.points.len() <= 1 || dt <= 0. {
+            return Vec2::ZERO;
+        }
+        self.index = self.index.min(self.points.len() - 1);
+
+        if self.wait > 0. {
+            self.wait -= dt;
+            return Vec2::ZERO;
+        }
+
+        let target = self.points[self.index];
+        let delta = target - position;
+        let distance = delta.length();
+
+        let mut speed = self.segment_speed(default_speed);
+        if self.easing {
+            let start = self.points[self.prev_index()];
+            let length = start.distance(target);
+            if length > f32::EPSILON {
+                let t = (1. - distance / length).clamp(0., 1.);
+                speed *= PATROL_EASE_MIN + (1. - PATROL_EASE_MIN) * (std::f32::consts::PI * t).sin();
+            }
+        }
+
+        if distance <= speed * dt {
+            self.wait = self.waits.get(self.index).copied().unwrap_or(0.);
+            self.advance();
+            return delta / dt;
+        }
+        delta / distance * speed
+    }
 }
 
 impl LdtkEntity for Patrol {
@@ -38,9 +452,11 @@ impl LdtkEntity for Patrol {
             entity_instance.pivot,
         ));
 
+        // 没有 patrol 字段时只有起点, 原地不动
         let ldtk_patrol_points = entity_instance
             .iter_points_field("patrol")
-            .expect("patrol field should be correclty typed");
+            .into_iter()
+            .flatten();
 
         for ldtk_point in ldtk_patrol_points {
             // The +1 is necessary here due to the pivot of the entities in the sample
@@ -59,44 +475,146 @@ impl LdtkEntity for Patrol {
             ));
         }
 
+        let floats = |name: &str| -> Vec<f32> {
+            entity_instance
+                .get_floats_field(name)
+                .map(|values| values.iter().map(|v| v.unwrap_or(0.)).collect())
+                .unwrap_or_default()
+        };
+        let mode = match entity_instance.get_enum_field("patrol_mode").map(String::as_str) {
+            Ok("Loop") => PatrolMode::Loop,
+            _ => PatrolMode::PingPong,
+        };
+
         Patrol {
-            points,
-            index: 1,
-            forward: true,
+            waits: floats("patrol_waits"),
+            speeds: floats("patrol_speeds"),
+            mode,
+            easing: entity_instance.get_bool_field("patrol_easing").copied().unwrap_or(false),
+            ..Patrol::new(points)
         }
     }
 }
 
 
-pub fn patrol(mut query: Query<(&mut Transform, &mut Velocity, &mut Patrol)>) {
-    for (mut transform, mut velocity, mut patrol) in &mut query {
+pub fn patrol(
+    time: Res<Time>,
+    mut query: Query<(&Transform, &mut Velocity, &mut Patrol, Option<&EnemyStats>, Option<&EnemyBrain>, Option<&mut Facing>)>,
+) {
+    for (transform, mut velocity, mut patrol, stats, brain, facing) in &mut query {
         if patrol.points.len() <= 1 {
             continue;
         }
+        if brain.is_some_and(|brain| brain.state != EnemyState::Patrol) {
+            continue;
+        }
+        let speed = stats.map(|stats| stats.speed).unwrap_or(EnemyStats::default().speed);
+        velocity.linvel = patrol.step(transform.translation.truncate(), time.delta_seconds(), speed);
+        if let (Some(mut facing), Some(new_facing)) = (facing, Facing::from_x(velocity.linvel.x)) {
+            facing.set_if_neq(new_facing);
+        }
+    }
+}
 
-        let mut new_velocity =
-            (patrol.points[patrol.index] - transform.translation.truncate()).normalize() * 75.;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if new_velocity.dot(velocity.linvel) < 0. {
-            if patrol.index == 0 {
-                patrol.forward = true;
-            } else if patrol.index == patrol.points.len() - 1 {
-                patrol.forward = false;
+    // 不经过 Rapier, 直接按返回的速度积分位置
+    fn run(patrol: &mut Patrol, mut position: Vec2, dt: f32, frames: usize) -> Vec<Vec2> {
+        let mut visited = vec![];
+        for _ in 0..frames {
+            let index = patrol.index;
+            position += patrol.step(position, dt, 10.) * dt;
+            if patrol.index != index {
+                visited.push(position);
             }
+        }
+        visited
+    }
 
-            transform.translation.x = patrol.points[patrol.index].x;
-            transform.translation.y = patrol.points[patrol.index].y;
+    fn points() -> Vec<Vec2> {
+        vec![Vec2::ZERO, Vec2::new(10., 0.), Vec2::new(10., 10.)]
+    }
 
-            if patrol.forward {
-                patrol.index += 1;
-            } else {
-                patrol.index -= 1;
-            }
+    #[test]
+    fn walk_dir_ignores_tiny_offsets() {
+        assert_eq!(walk_dir(0.), 0.);
+        assert_eq!(walk_dir(1e-6), 0.);
+        assert_eq!(walk_dir(0.5), 1.);
+        assert_eq!(walk_dir(-0.5), -1.);
+    }
 
-            new_velocity =
-                (patrol.points[patrol.index] - transform.translation.truncate()).normalize() * 75.;
+    #[test]
+    fn ping_pong_reverses_at_ends() {
+        let mut patrol = Patrol::new(points());
+        let visited = run(&mut patrol, Vec2::ZERO, 0.1, 80);
+        let expected = [points()[1], points()[2], points()[1], points()[0], points()[1]];
+        assert!(visited.len() >= expected.len());
+        for (a, b) in visited.iter().zip(expected.iter()) {
+            assert!(a.distance(*b) < 1e-4, "{:?} != {:?}", a, b);
         }
+    }
+
+    #[test]
+    fn loop_returns_to_first_point() {
+        let mut patrol = Patrol {
+            mode: PatrolMode::Loop,
+            ..Patrol::new(points())
+        };
+        let visited = run(&mut patrol, Vec2::ZERO, 0.1, 60);
+        assert!(visited[2].distance(Vec2::ZERO) < 1e-4);
+        assert!(visited[3].distance(points()[1]) < 1e-4);
+    }
+
+    #[test]
+    fn waits_at_point_before_moving_on() {
+        let mut patrol = Patrol {
+            waits: vec![0., 1.],
+            ..Patrol::new(points())
+        };
+        let arrive = patrol.step(Vec2::new(9.5, 0.), 0.1, 10.);
+        assert_eq!(arrive, Vec2::new(5., 0.));
+        assert_eq!(patrol.index, 2);
+        for _ in 0..9 {
+            assert_eq!(patrol.step(Vec2::new(10., 0.), 0.1, 10.), Vec2::ZERO);
+        }
+        let mut moved = Vec2::ZERO;
+        for _ in 0..2 {
+            moved = patrol.step(Vec2::new(10., 0.), 0.1, 10.);
+        }
+        assert_eq!(moved, Vec2::new(0., 10.));
+    }
+
+    #[test]
+    fn segment_speed_overrides_default() {
+        let mut patrol = Patrol {
+            speeds: vec![20., 0.],
+            ..Patrol::new(points())
+        };
+        assert_eq!(patrol.step(Vec2::ZERO, 0.1, 10.), Vec2::new(20., 0.));
+        patrol.index = 2;
+        assert_eq!(patrol.step(Vec2::new(10., 0.), 0.1, 10.), Vec2::new(0., 10.));
+    }
+
+    #[test]
+    fn easing_is_slower_at_segment_ends() {
+        let mut patrol = Patrol {
+            easing: true,
+            ..Patrol::new(points())
+        };
+        let start = patrol.step(Vec2::ZERO, 0.01, 10.).length();
+        let middle = patrol.step(Vec2::new(5., 0.), 0.01, 10.).length();
+        assert!(start < middle);
+        assert!((middle - 10.).abs() < 1e-4);
+    }
 
-        velocity.linvel = new_velocity;
+    #[test]
+    fn standing_on_target_does_not_produce_nan() {
+        let mut patrol = Patrol::new(vec![Vec2::ZERO, Vec2::ZERO]);
+        let velocity = patrol.step(Vec2::ZERO, 0.1, 10.);
+        assert!(!velocity.is_nan());
+        assert_eq!(patrol.index, 0);
+        assert!(!patrol.step(Vec2::ZERO, 0.1, 10.).is_nan());
     }
 }
@@ -1,16 +1,22 @@
 use bevy::prelude::*;
+#[cfg(feature = "dev")]
 use bevy_inspector_egui::prelude::*;
-use bevy_inspector_egui::quick::ResourceInspectorPlugin;
 
 pub const RAPIER_LENGTH_UNIT: f32 = 100.0;
 
-#[derive(Reflect, Resource, Default, InspectorOptions)]
-#[reflect(Resource, InspectorOptions)]
+#[derive(Reflect, Resource, Default)]
+#[reflect(Resource)]
+#[cfg_attr(feature = "dev", derive(InspectorOptions), reflect(InspectorOptions))]
 pub struct GlobalData {
     pub gravity: Vec2,
     pub scale: f32,
 }
 
+#[derive(Reflect, Resource, Default)]
+#[reflect(Resource)]
+#[cfg_attr(feature = "dev", derive(InspectorOptions), reflect(InspectorOptions))]
+pub struct Score(pub u32);
+
 pub fn global_plugin(app: &mut App) {
     app
     .insert_resource(
@@ -19,5 +25,13 @@ pub fn global_plugin(app: &mut App) {
             scale: 1.0,
         }
     )
-    .register_type::<GlobalData>();
+    .init_resource::<Score>();
+
+    // 只有编辑器和 inspector 需要反射注册
+    #[cfg(feature = "dev")]
+    {
+        app
+            .register_type::<GlobalData>()
+            .register_type::<Score>();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,367 @@
+// HUD
+// 左上角显示生命, 分数, 高度和当前道具, 右侧为整个攀爬过程的小地图
+// 只在对应数据变化时更新
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::comm::Health;
+use crate::global::Score;
+use crate::locale::Locale;
+use crate::player::Player;
+use crate::roulette::{find_item_info, Roulette, RouletteItemInfo};
+
+pub fn hud_plugin(app: &mut App) {
+    app
+        .add_systems(Startup, setup_hud)
+        .add_systems(Update, (
+            update_health,
+            update_score,
+            update_altitude,
+            update_item,
+            build_minimap,
+            update_minimap,
+        ));
+}
+
+const HUD_FONT: &str = "fonts/FiraSans-Bold.ttf";
+const HUD_FONT_SIZE: f32 = 20.;
+const HEALTH_BAR_WIDTH: f32 = 160.;
+const ITEM_ICON_SIZE: f32 = 40.;
+const MINIMAP_WIDTH: f32 = 12.;
+const MINIMAP_HEIGHT: f32 = 240.;
+const MINIMAP_MARKER_SIZE: f32 = 8.;
+// 多少像素算 1 米
+const ALTITUDE_UNIT: f32 = 16.;
+
+#[derive(Component)]
+struct HudHealthFill;
+
+#[derive(Component)]
+struct HudHealthText;
+
+#[derive(Component)]
+struct HudScoreText;
+
+#[derive(Component)]
+struct HudAltitudeText;
+
+#[derive(Component)]
+struct HudItemIcon;
+
+#[derive(Component)]
+struct HudItemText;
+
+#[derive(Component)]
+struct HudMinimap;
+
+#[derive(Component)]
+struct HudMinimapMarker;
+
+#[derive(Component)]
+struct HudMinimapLevel;
+
+// 所有关卡在世界中的纵向范围, 放在小地图上, 用于高度和小地图
+#[derive(Component, Clone, Copy, Debug)]
+struct AscentRange {
+    min: f32,
+    max: f32,
+}
+
+impl AscentRange {
+    // 0 为最低处, 1 为最高处
+    fn progress(&self, y: f32) -> f32 {
+        if self.max <= self.min {
+            return 0.;
+        }
+        ((y - self.min) / (self.max - self.min)).clamp(0., 1.)
+    }
+}
+
+fn text(value: impl Into<String>, font: &Handle<Font>) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font: font.clone(),
+            font_size: HUD_FONT_SIZE,
+            color: Color::WHITE,
+        },
+    )
+}
+
+fn setup_hud(mut cmds: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(HUD_FONT);
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                left: Val::Px(12.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            ..default()
+        },
+        Name::new("hud"),
+    )).with_children(|parent| {
+        // 生命
+        parent.spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(HEALTH_BAR_WIDTH),
+                    height: Val::Px(12.),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..default()
+            }).with_children(|parent| {
+                parent.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.),
+                            height: Val::Percent(100.),
+                            ..default()
+                        },
+                        background_color: Color::LIME_GREEN.into(),
+                        ..default()
+                    },
+                    HudHealthFill,
+                ));
+            });
+            parent.spawn((text("", &font), HudHealthText));
+        });
+        parent.spawn((text("", &font), HudScoreText));
+        parent.spawn((text("", &font), HudAltitudeText));
+        // 当前道具和剩余次数
+        parent.spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((
+                ImageBundle {
+                    style: Style {
+                        width: Val::Px(ITEM_ICON_SIZE),
+                        height: Val::Px(ITEM_ICON_SIZE),
+                        ..default()
+                    },
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                HudItemIcon,
+            ));
+            parent.spawn((text("", &font), HudItemText));
+        });
+    });
+
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                right: Val::Px(12.),
+                width: Val::Px(MINIMAP_WIDTH),
+                height: Val::Px(MINIMAP_HEIGHT),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.4).into(),
+            ..default()
+        },
+        HudMinimap,
+        Name::new("hud_minimap"),
+    )).with_children(|parent| {
+        parent.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px((MINIMAP_WIDTH - MINIMAP_MARKER_SIZE) / 2.),
+                    bottom: Val::Px(0.),
+                    width: Val::Px(MINIMAP_MARKER_SIZE),
+                    height: Val::Px(MINIMAP_MARKER_SIZE),
+                    ..default()
+                },
+                background_color: Color::GOLD.into(),
+                z_index: ZIndex::Local(1),
+                ..default()
+            },
+            HudMinimapMarker,
+        ));
+    });
+}
+
+fn update_health(
+    query_player: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut query_fill: Query<&mut Style, With<HudHealthFill>>,
+    mut query_text: Query<&mut Text, With<HudHealthText>>,
+) {
+    let Ok(health) = query_player.get_single() else {
+        return;
+    };
+    let ratio = if health.max > 0. { (health.current / health.max).clamp(0., 1.) } else { 0. };
+    for mut style in query_fill.iter_mut() {
+        style.width = Val::Percent(ratio * 100.);
+    }
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = format!("{}/{}", health.current.max(0.).ceil(), health.max.ceil());
+    }
+}
+
+fn update_score(
+    score: Res<Score>,
+    locale: Res<Locale>,
+    mut query_text: Query<&mut Text, With<HudScoreText>>,
+) {
+    if !score.is_changed() && !locale.is_changed() {
+        return;
+    }
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = locale.format("hud.score", score.0);
+    }
+}
+
+// 玩家移动时只有显示的整数米数变化才更新文字
+fn update_altitude(
+    query_player: Query<Ref<Transform>, With<Player>>,
+    query_range: Query<Ref<AscentRange>>,
+    locale: Res<Locale>,
+    mut query_text: Query<&mut Text, With<HudAltitudeText>>,
+    mut shown: Local<Option<i32>>,
+) {
+    let (Ok(transform), Ok(range)) = (query_player.get_single(), query_range.get_single()) else {
+        return;
+    };
+    if !transform.is_changed() && !range.is_changed() && !locale.is_changed() {
+        return;
+    }
+    let altitude = ((transform.translation.y - range.min) / ALTITUDE_UNIT).floor() as i32;
+    if *shown == Some(altitude) && !locale.is_changed() {
+        return;
+    }
+    *shown = Some(altitude);
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = locale.format("hud.altitude", altitude.max(0));
+    }
+}
+
+fn update_item(
+    asset_server: Res<AssetServer>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+    query_roulette: Query<Ref<Roulette>>,
+    mut query_icon: Query<(&mut UiImage, &mut Visibility), With<HudItemIcon>>,
+    mut query_text: Query<&mut Text, With<HudItemText>>,
+) {
+    let Ok(roulette) = query_roulette.get_single() else {
+        return;
+    };
+    // 道具配置可能比转盘晚加载
+    if !roulette.is_changed() && !item_infos.is_changed() {
+        return;
+    }
+    let slot = roulette.get_cur_item();
+    let info = slot.and_then(|slot| find_item_info(&item_infos, slot.id));
+    for (mut image, mut visibility) in query_icon.iter_mut() {
+        match info {
+            Some(info) => {
+                image.texture = asset_server.load(info.texture.clone());
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    let label = match slot {
+        Some(slot) if slot.max_charges > 0 => format!("{}/{}", slot.charges, slot.max_charges),
+        Some(slot) if slot.count > 1 => format!("x{}", slot.count),
+        _ => String::new(),
+    };
+    for mut text in query_text.iter_mut() {
+        text.sections[0].value = label.clone();
+    }
+}
+
+// LDtk 项目加载后按各关卡的纵向范围画出小地图的分段
+fn build_minimap(
+    mut cmds: Commands,
+    mut project_events: EventReader<AssetEvent<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    query_minimap: Query<Entity, With<HudMinimap>>,
+    query_segment: Query<Entity, With<HudMinimapLevel>>,
+) {
+    for event in project_events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        let (Some(project), Ok(minimap)) = (ldtk_project_assets.get(*id), query_minimap.get_single()) else {
+            continue;
+        };
+        // LDtk 的 y 轴向下, 关卡在 Bevy 中的范围为 [-world_y - px_hei, -world_y]
+        let spans: Vec<(f32, f32)> = project
+            .iter_raw_levels()
+            .map(|level| {
+                let top = -level.world_y as f32;
+                (top - level.px_hei as f32, top)
+            })
+            .collect();
+        let Some((min, max)) = spans.iter().copied().reduce(|a, b| (a.0.min(b.0), a.1.max(b.1))) else {
+            continue;
+        };
+        let range = AscentRange { min, max };
+
+        for entity in query_segment.iter() {
+            cmds.entity(entity).despawn_recursive();
+        }
+        cmds.entity(minimap).insert(range).with_children(|parent| {
+            for (i, (low, high)) in spans.iter().enumerate() {
+                let bottom = range.progress(*low) * MINIMAP_HEIGHT;
+                let top = range.progress(*high) * MINIMAP_HEIGHT;
+                // 相邻关卡深浅交替
+                let alpha = if i % 2 == 0 { 0.35 } else { 0.2 };
+                parent.spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Px(0.),
+                            bottom: Val::Px(bottom),
+                            width: Val::Percent(100.),
+                            height: Val::Px((top - bottom).max(1.)),
+                            ..default()
+                        },
+                        background_color: Color::rgba(1., 1., 1., alpha).into(),
+                        ..default()
+                    },
+                    HudMinimapLevel,
+                ));
+            }
+        });
+    }
+}
+
+fn update_minimap(
+    query_player: Query<Ref<Transform>, With<Player>>,
+    query_range: Query<Ref<AscentRange>>,
+    mut query_marker: Query<&mut Style, With<HudMinimapMarker>>,
+) {
+    let (Ok(transform), Ok(range)) = (query_player.get_single(), query_range.get_single()) else {
+        return;
+    };
+    if !transform.is_changed() && !range.is_changed() {
+        return;
+    }
+    let bottom = range.progress(transform.translation.y) * (MINIMAP_HEIGHT - MINIMAP_MARKER_SIZE);
+    let value = Val::Px(bottom.round());
+    for mut style in query_marker.iter_mut() {
+        if style.bottom != value {
+            style.bottom = value;
+        }
+    }
+}
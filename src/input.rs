@@ -1,47 +1,264 @@
-
-
+use bevy::ecs::system::SystemParam;
+use bevy::input::mouse::MouseWheel;
+use bevy::input::InputSystem;
 use bevy::prelude::*;
-use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{Settings, SettingsMenu};
 
 pub fn input_plugin(app: &mut App) {
     app
-    .add_plugins(InputManagerPlugin::<Action>::default());
+        .init_resource::<ButtonInput<WheelDirection>>()
+        .add_systems(PreUpdate, update_wheel_input.after(InputSystem));
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Actionlike, Reflect)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy, Reflect, Serialize, Deserialize)]
 pub enum Action {
     Jump,
     LeftMove,
     RightMove,
     LeftShoot,
+    ClimbUp,
+    ClimbDown,
+    Spin,
+    Interact,
+    // 打开或关闭设置界面, 同时暂停游戏
+    Pause,
+    // 轮盘满时用新物品替换当前物品或丢弃新物品
+    SwapItem,
+    DiscardItem,
+    RotateLeft,
+    RotateRight,
 }
 
-const PLAYER_INPUT_KEY_COUNT: usize = 4;
-const PLAYER_INPUT_MAP_1: [(Action, InputKind); PLAYER_INPUT_KEY_COUNT]  = [
-    (Action::Jump, InputKind::PhysicalKey(KeyCode::Space)),
-    (Action::LeftMove, InputKind::PhysicalKey(KeyCode::KeyA)),
-    (Action::RightMove, InputKind::PhysicalKey(KeyCode::KeyD)),
-    (Action::LeftShoot, InputKind::Mouse(MouseButton::Left)),
+impl Action {
+    pub const ALL: [Action; ACTION_COUNT] = [
+        Action::Jump,
+        Action::LeftMove,
+        Action::RightMove,
+        Action::LeftShoot,
+        Action::ClimbUp,
+        Action::ClimbDown,
+        Action::Spin,
+        Action::Interact,
+        Action::Pause,
+        Action::SwapItem,
+        Action::DiscardItem,
+        Action::RotateLeft,
+        Action::RotateRight,
+    ];
+}
+
+const ACTION_COUNT: usize = 13;
+const DEFAULT_BINDINGS: [(Action, Binding); ACTION_COUNT] = [
+    (Action::Jump, Binding::Key(KeyCode::Space)),
+    (Action::LeftMove, Binding::Key(KeyCode::KeyA)),
+    (Action::RightMove, Binding::Key(KeyCode::KeyD)),
+    (Action::LeftShoot, Binding::Mouse(MouseButton::Left)),
+    (Action::ClimbUp, Binding::Key(KeyCode::KeyW)),
+    (Action::ClimbDown, Binding::Key(KeyCode::KeyS)),
+    (Action::Spin, Binding::Mouse(MouseButton::Right)),
+    (Action::Interact, Binding::Key(KeyCode::KeyF)),
+    (Action::Pause, Binding::Key(KeyCode::Escape)),
+    (Action::SwapItem, Binding::Key(KeyCode::KeyR)),
+    (Action::DiscardItem, Binding::Key(KeyCode::KeyX)),
+    (Action::RotateLeft, Binding::Wheel(WheelDirection::Up)),
+    (Action::RotateRight, Binding::Wheel(WheelDirection::Down)),
 ];
 
-pub struct PlayerInputMap {
-    map: Vec<(Action, InputKind)>,
+// 滚轮的一格当作按下一帧的按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WheelDirection {
+    Up,
+    Down,
 }
 
-impl Default for PlayerInputMap {
+fn update_wheel_input(mut wheel: ResMut<ButtonInput<WheelDirection>>, mut events: EventReader<MouseWheel>) {
+    wheel.release_all();
+    wheel.clear();
+    for event in events.read() {
+        if event.y > 0. {
+            wheel.press(WheelDirection::Up);
+        } else if event.y < 0. {
+            wheel.press(WheelDirection::Down);
+        }
+    }
+}
+
+// 可以保存到配置文件的按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+}
+
+impl Binding {
+    pub fn pressed(
+        self,
+        keys: &ButtonInput<KeyCode>,
+        mouse: &ButtonInput<MouseButton>,
+        wheel: &ButtonInput<WheelDirection>,
+    ) -> bool {
+        match self {
+            Binding::Key(key) => keys.pressed(key),
+            Binding::Mouse(button) => mouse.pressed(button),
+            Binding::Wheel(direction) => wheel.pressed(direction),
+        }
+    }
+
+    pub fn just_pressed(
+        self,
+        keys: &ButtonInput<KeyCode>,
+        mouse: &ButtonInput<MouseButton>,
+        wheel: &ButtonInput<WheelDirection>,
+    ) -> bool {
+        match self {
+            Binding::Key(key) => keys.just_pressed(key),
+            Binding::Mouse(button) => mouse.just_pressed(button),
+            Binding::Wheel(direction) => wheel.just_pressed(direction),
+        }
+    }
+
+    // 设置界面上显示的名字, 去掉 KeyCode 的 Key / Digit 前缀
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => {
+                let name = format!("{:?}", key);
+                name.strip_prefix("Key")
+                    .or_else(|| name.strip_prefix("Digit"))
+                    .unwrap_or(&name)
+                    .to_string()
+            }
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Wheel(direction) => format!("Wheel {:?}", direction),
+        }
+    }
+}
+
+// 每个动作对应一个按键, 配置中缺少的动作使用默认按键
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings(Vec<(Action, Binding)>);
+
+impl Default for KeyBindings {
     fn default() -> Self {
-        let map = Vec::from(PLAYER_INPUT_MAP_1);
-        Self {
-            map
+        Self(DEFAULT_BINDINGS.to_vec())
+    }
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> Option<Binding> {
+        let find = |bindings: &[(Action, Binding)]| bindings.iter().find(|(a, _)| *a == action).map(|(_, b)| *b);
+        find(&self.0).or_else(|| find(&DEFAULT_BINDINGS))
+    }
+
+    // 按键已被其他动作使用时两者交换
+    // 先补全配置中缺少的默认按键, 否则与默认按键冲突时不会交换
+    pub fn set(&mut self, action: Action, binding: Binding) {
+        for (default_action, default_binding) in DEFAULT_BINDINGS {
+            if !self.0.iter().any(|(a, _)| *a == default_action) {
+                self.0.push((default_action, default_binding));
+            }
+        }
+        let previous = self.get(action);
+        for (other, other_binding) in self.0.iter_mut() {
+            if *other != action && *other_binding == binding {
+                if let Some(previous) = previous {
+                    *other_binding = previous;
+                }
+            }
+        }
+        match self.0.iter_mut().find(|(a, _)| *a == action) {
+            Some((_, b)) => *b = binding,
+            None => self.0.push((action, binding)),
+        }
+    }
+}
+
+// 按当前按键设置读取动作, 设置界面打开时不响应
+#[derive(SystemParam)]
+pub struct ActionInput<'w> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    wheel: Res<'w, ButtonInput<WheelDirection>>,
+    settings: Res<'w, Settings>,
+    menu: Res<'w, SettingsMenu>,
+}
+
+impl<'w> ActionInput<'w> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.check(action, Binding::pressed)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.check(action, Binding::just_pressed)
+    }
+
+    fn check(
+        &self,
+        action: Action,
+        test: impl Fn(Binding, &ButtonInput<KeyCode>, &ButtonInput<MouseButton>, &ButtonInput<WheelDirection>) -> bool,
+    ) -> bool {
+        if self.menu.open {
+            return false;
         }
+        self.settings
+            .bindings
+            .get(action)
+            .is_some_and(|binding| test(binding, &self.keys, &self.mouse, &self.wheel))
     }
 }
 
-impl IntoIterator for PlayerInputMap {
-    type Item = (Action, InputKind);
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_falls_back_to_default_binding() {
+        let bindings = KeyBindings(vec![(Action::Jump, Binding::Key(KeyCode::KeyK))]);
+        assert_eq!(bindings.get(Action::Jump), Some(Binding::Key(KeyCode::KeyK)));
+        assert_eq!(bindings.get(Action::LeftMove), Some(Binding::Key(KeyCode::KeyA)));
+        assert_eq!(bindings.get(Action::Pause), Some(Binding::Key(KeyCode::Escape)));
+    }
+
+    #[test]
+    fn set_swaps_with_stored_binding() {
+        let mut bindings = KeyBindings::default();
+        bindings.set(Action::Jump, Binding::Key(KeyCode::KeyA));
+        assert_eq!(bindings.get(Action::Jump), Some(Binding::Key(KeyCode::KeyA)));
+        assert_eq!(bindings.get(Action::LeftMove), Some(Binding::Key(KeyCode::Space)));
+    }
+
+    #[test]
+    fn set_swaps_with_default_only_binding() {
+        // 旧配置中只保存了部分动作
+        let mut bindings = KeyBindings(vec![(Action::Jump, Binding::Key(KeyCode::Space))]);
+        bindings.set(Action::Jump, Binding::Key(KeyCode::KeyD));
+        assert_eq!(bindings.get(Action::Jump), Some(Binding::Key(KeyCode::KeyD)));
+        assert_eq!(bindings.get(Action::RightMove), Some(Binding::Key(KeyCode::Space)));
+        let users = Action::ALL
+            .iter()
+            .filter(|action| bindings.get(**action) == Some(Binding::Key(KeyCode::KeyD)))
+            .count();
+        assert_eq!(users, 1);
+    }
+
+    #[test]
+    fn wheel_bindings_swap_like_keys() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.get(Action::RotateLeft), Some(Binding::Wheel(WheelDirection::Up)));
+        bindings.set(Action::Spin, Binding::Wheel(WheelDirection::Up));
+        assert_eq!(bindings.get(Action::Spin), Some(Binding::Wheel(WheelDirection::Up)));
+        assert_eq!(bindings.get(Action::RotateLeft), Some(Binding::Mouse(MouseButton::Right)));
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.map.into_iter()
+    #[test]
+    fn set_unused_binding_keeps_others() {
+        let mut bindings = KeyBindings::default();
+        bindings.set(Action::Pause, Binding::Key(KeyCode::KeyP));
+        assert_eq!(bindings.get(Action::Pause), Some(Binding::Key(KeyCode::KeyP)));
+        for action in Action::ALL.into_iter().filter(|a| *a != Action::Pause) {
+            assert_eq!(bindings.get(action), KeyBindings::default().get(action));
+        }
     }
-}
\ No newline at end of file
+}
@@ -5,8 +5,13 @@ use crate::wall::WallBundle;
 use crate::scene::LadderBundle;
 use crate::player::PlayerBundle;
 use crate::scene::MobBundle;
+use crate::scene::FlyerBundle;
+use crate::scene::TurretBundle;
 use crate::scene::ChestBundle;
 use crate::scene::PumpkinsBundle;
+use crate::pickup::PickupBundle;
+use crate::spawner::SpawnerBundle;
+use crate::boss::BossBundle;
 
 
 pub fn ldtk_plugin(app: &mut App) {
@@ -16,6 +21,11 @@ pub fn ldtk_plugin(app: &mut App) {
         .register_ldtk_int_cell::<WallBundle>(3)
         .register_ldtk_entity::<PlayerBundle>("Player")
         .register_ldtk_entity::<MobBundle>("Mob")
+        .register_ldtk_entity::<FlyerBundle>("Flyer")
+        .register_ldtk_entity::<TurretBundle>("Turret")
         .register_ldtk_entity::<ChestBundle>("Chest")
-        .register_ldtk_entity::<PumpkinsBundle>("Pumpkins");
+        .register_ldtk_entity::<PumpkinsBundle>("Pumpkins")
+        .register_ldtk_entity::<PickupBundle>("Pickup")
+        .register_ldtk_entity::<SpawnerBundle>("Spawner")
+        .register_ldtk_entity::<BossBundle>("Boss");
 }
@@ -0,0 +1,336 @@
+// 多语言
+// 字符串表为 assets/locale/<语言>.csv, 两列 key,text, 文字中的 {} 由 format 替换
+// 当前语言缺少的 key 回退到英文, 再回退到 key 本身; 物品名的 key 为 item.<id>
+// FiraSans 没有中日韩字形, 含这些字符的文字改用 CJK 字体; CJK 字体加载成功前不提供中文
+
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_common_assets::csv::LoadedCsv;
+use serde::{Deserialize, Serialize};
+
+use crate::roulette::RouletteItemInfo;
+use crate::settings::Settings;
+
+pub fn locale_plugin(app: &mut App) {
+    app
+        .init_resource::<Locale>()
+        .add_systems(Startup, setup_locale)
+        .add_systems(Update, (
+            rebuild_locale,
+            update_localized_text,
+            check_cjk_font,
+            apply_font_fallback,
+        ).chain());
+}
+
+const DEFAULT_FONT: &str = "fonts/FiraSans-Bold.ttf";
+// 需要放入 assets/fonts, 缺少时警告一次, 设置中不能选择中文
+const CJK_FONT: &str = "fonts/NotoSansSC-Bold.otf";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::En, Language::Zh];
+
+    fn table(self) -> &'static str {
+        match self {
+            Language::En => "locale/en.csv",
+            Language::Zh => "locale/zh.csv",
+        }
+    }
+
+    // 语言名本身的 key
+    pub fn key(self) -> &'static str {
+        match self {
+            Language::En => "language.en",
+            Language::Zh => "language.zh",
+        }
+    }
+
+    // 需要 CJK 字体才能显示
+    pub fn needs_cjk(self) -> bool {
+        matches!(self, Language::Zh)
+    }
+
+    pub fn next(self, step: i32) -> Language {
+        let len = Language::ALL.len() as i32;
+        let index = Language::ALL.iter().position(|l| *l == self).unwrap_or(0) as i32;
+        Language::ALL[(index + step).rem_euclid(len) as usize]
+    }
+}
+
+// 字符串表中的一行
+#[derive(Serialize, Deserialize, Debug, TypePath, Asset, Clone)]
+pub struct LocaleEntry {
+    pub key: String,
+    pub text: String,
+}
+
+#[derive(Resource, Default)]
+struct LocaleTables(HashMap<Language, Handle<LoadedCsv<LocaleEntry>>>);
+
+#[derive(Resource, Default)]
+pub struct Locale {
+    pub language: Language,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+    font: Handle<Font>,
+    cjk_font: Handle<Font>,
+    cjk_ready: bool,
+}
+
+impl Locale {
+    pub fn get(&self, key: &str) -> String {
+        self.lookup(key).unwrap_or(key).to_string()
+    }
+
+    // 把文字中的 {} 替换为 value
+    pub fn format(&self, key: &str, value: impl std::fmt::Display) -> String {
+        self.get(key).replacen("{}", &value.to_string(), 1)
+    }
+
+    // 没有翻译时使用配置中的名字
+    pub fn item_name(&self, info: &RouletteItemInfo) -> String {
+        self.lookup(&format!("item.{}", info.id))
+            .unwrap_or(&info.name)
+            .to_string()
+    }
+
+    // 含中日韩字符的文字使用 CJK 字体
+    pub fn font_for(&self, text: &str) -> Handle<Font> {
+        if text.chars().any(is_cjk) {
+            self.cjk_font.clone()
+        } else {
+            self.font.clone()
+        }
+    }
+
+    pub fn is_available(&self, language: Language) -> bool {
+        !language.needs_cjk() || self.cjk_ready
+    }
+
+    // 按 step 切换语言, 跳过当前无法显示的语言
+    pub fn next_language(&self, language: Language, step: i32) -> Language {
+        let mut next = language;
+        for _ in 0..Language::ALL.len() {
+            next = next.next(step);
+            if self.is_available(next) {
+                return next;
+            }
+        }
+        language
+    }
+
+    fn lookup(&self, key: &str) -> Option<&str> {
+        self.strings
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x30FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xAC00..=0xD7AF
+        | 0xF900..=0xFAFF
+        | 0xFF00..=0xFFEF
+    )
+}
+
+// 文字为 key 对应的翻译, 切换语言时自动更新
+#[derive(Component, Clone, Debug)]
+pub struct LocalizedText(pub String);
+
+impl LocalizedText {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+}
+
+fn setup_locale(
+    mut cmds: Commands,
+    asset_server: Res<AssetServer>,
+    mut locale: ResMut<Locale>,
+) {
+    locale.font = asset_server.load(DEFAULT_FONT);
+    locale.cjk_font = asset_server.load(CJK_FONT);
+    let tables = Language::ALL
+        .iter()
+        .map(|language| (*language, asset_server.load(language.table())))
+        .collect();
+    cmds.insert_resource(LocaleTables(tables));
+}
+
+fn table_strings(
+    language: Language,
+    tables: &LocaleTables,
+    loaded: &Assets<LoadedCsv<LocaleEntry>>,
+    entries: &Assets<LocaleEntry>,
+) -> HashMap<String, String> {
+    tables
+        .0
+        .get(&language)
+        .and_then(|handle| loaded.get(handle))
+        .map(|csv| {
+            csv.rows
+                .iter()
+                .filter_map(|row| entries.get(row))
+                .map(|entry| (entry.key.clone(), entry.text.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 语言设置变化, 字符串表加载或 CJK 字体加载后重建
+// 设置的语言无法显示时使用英文, 不修改设置
+fn rebuild_locale(
+    settings: Res<Settings>,
+    tables: Option<Res<LocaleTables>>,
+    mut csv_events: EventReader<AssetEvent<LoadedCsv<LocaleEntry>>>,
+    loaded: Res<Assets<LoadedCsv<LocaleEntry>>>,
+    entries: Res<Assets<LocaleEntry>>,
+    mut locale: ResMut<Locale>,
+) {
+    let Some(tables) = tables else {
+        return;
+    };
+    let table_loaded = csv_events
+        .read()
+        .any(|e| matches!(e, AssetEvent::LoadedWithDependencies { .. } | AssetEvent::Modified { .. }));
+    let language = if locale.is_available(settings.language) {
+        settings.language
+    } else {
+        Language::En
+    };
+    if !table_loaded && locale.language == language {
+        return;
+    }
+    locale.language = language;
+    locale.strings = table_strings(language, &tables, &loaded, &entries);
+    locale.fallback = table_strings(Language::En, &tables, &loaded, &entries);
+}
+
+fn update_localized_text(
+    locale: Res<Locale>,
+    mut query: Query<(Ref<LocalizedText>, &mut Text)>,
+) {
+    for (key, mut text) in query.iter_mut() {
+        if !locale.is_changed() && !key.is_changed() {
+            continue;
+        }
+        let value = locale.get(&key.0);
+        if let Some(section) = text.sections.first_mut() {
+            section.value = value;
+        }
+    }
+}
+
+// CJK 字体加载成功后才能选择中文, 加载失败时改用默认字体
+fn check_cjk_font(asset_server: Res<AssetServer>, mut locale: ResMut<Locale>, mut failed: Local<bool>) {
+    if locale.cjk_ready || *failed {
+        return;
+    }
+    match asset_server.get_load_state(locale.cjk_font.id()) {
+        Some(LoadState::Loaded) => locale.cjk_ready = true,
+        Some(LoadState::Failed) => {
+            warn!("failed to load {}, Chinese is unavailable", CJK_FONT);
+            *failed = true;
+            locale.cjk_font = locale.font.clone();
+        }
+        _ => {}
+    }
+}
+
+// 只替换默认字体和本模块的字体, 其他指定了字体的文字保持不变
+fn apply_font_fallback(
+    locale: Res<Locale>,
+    mut query: Query<&mut Text, Changed<Text>>,
+) {
+    let default_font = Handle::<Font>::default();
+    for mut text in query.iter_mut() {
+        let replace: Vec<(usize, Handle<Font>)> = text
+            .sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| {
+                let font = &section.style.font;
+                *font == default_font || *font == locale.font || *font == locale.cjk_font
+            })
+            .map(|(i, section)| (i, locale.font_for(&section.value)))
+            .filter(|(i, font)| text.sections[*i].style.font != *font)
+            .collect();
+        for (i, font) in replace {
+            text.sections[i].style.font = font;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn next_language_wraps() {
+        assert_eq!(Language::En.next(1), Language::Zh);
+        assert_eq!(Language::Zh.next(1), Language::En);
+        assert_eq!(Language::En.next(-1), Language::Zh);
+        assert_eq!(Language::Zh.next(2), Language::Zh);
+    }
+
+    #[test]
+    fn next_language_skips_unavailable() {
+        let mut locale = Locale::default();
+        assert!(!locale.is_available(Language::Zh));
+        assert_eq!(locale.next_language(Language::En, 1), Language::En);
+        assert_eq!(locale.next_language(Language::En, -1), Language::En);
+        locale.cjk_ready = true;
+        assert_eq!(locale.next_language(Language::En, 1), Language::Zh);
+    }
+
+    #[test]
+    fn cjk_ranges() {
+        assert!(is_cjk('中'));
+        assert!(is_cjk('あ'));
+        assert!(is_cjk('한'));
+        assert!(is_cjk('，'));
+        assert!(!is_cjk('a'));
+        assert!(!is_cjk('é'));
+        assert!(!is_cjk(' '));
+    }
+
+    #[test]
+    fn lookup_falls_back_to_english_then_key() {
+        let locale = Locale {
+            language: Language::Zh,
+            strings: strings(&[("menu.title", "设置")]),
+            fallback: strings(&[("menu.title", "Settings"), ("menu.back", "Back")]),
+            ..default()
+        };
+        assert_eq!(locale.get("menu.title"), "设置");
+        assert_eq!(locale.get("menu.back"), "Back");
+        assert_eq!(locale.get("menu.missing"), "menu.missing");
+    }
+
+    #[test]
+    fn format_replaces_first_placeholder() {
+        let locale = Locale {
+            strings: strings(&[("hud.score", "Score {} / {}")]),
+            ..default()
+        };
+        assert_eq!(locale.format("hud.score", 12), "Score 12 / {}");
+        assert_eq!(locale.format("hud.missing", 3), "hud.missing");
+    }
+}
@@ -0,0 +1,79 @@
+// 日志
+// 各模块用 bevy 的 info!/debug! 等宏输出, target 为模块路径, 例如 ascend::roulette
+// 过滤规则与 RUST_LOG 相同, 修改 LogFilter 资源后立即生效
+
+use std::sync::OnceLock;
+
+use bevy::log::tracing_subscriber::layer::SubscriberExt;
+use bevy::log::tracing_subscriber::{reload, EnvFilter};
+use bevy::log::{BoxedSubscriber, Level, LogPlugin};
+use bevy::prelude::*;
+
+#[cfg(feature = "dev")]
+use crate::console::{AddConsoleCommand, ConsoleResult};
+
+pub fn logging_plugin(app: &mut App) {
+    app
+        .insert_resource(LogFilter(initial_filter()))
+        .add_systems(Update, apply_log_filter);
+
+    #[cfg(feature = "dev")]
+    {
+        app.add_console_command("log", "log [filter]: 查看或设置日志过滤, 例如 log warn,ascend::roulette=debug", console_log);
+    }
+}
+
+pub const DEFAULT_LOG_FILTER: &str = "info,wgpu=error,naga=warn,ascend=info";
+
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, BoxedSubscriber>> = OnceLock::new();
+
+// 替换 DefaultPlugins 中的 LogPlugin
+// LogPlugin 自己的过滤放开到 trace, 实际过滤由最外层可重新加载的 EnvFilter 决定
+pub fn log_plugin() -> LogPlugin {
+    LogPlugin {
+        level: Level::TRACE,
+        update_subscriber: Some(add_reload_filter),
+        ..default()
+    }
+}
+
+fn initial_filter() -> String {
+    std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string())
+}
+
+fn add_reload_filter(subscriber: BoxedSubscriber) -> BoxedSubscriber {
+    let filter = EnvFilter::try_new(initial_filter()).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let (layer, handle) = reload::Layer::new(filter);
+    let _ = FILTER_HANDLE.set(handle);
+    Box::new(subscriber.with(layer))
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct LogFilter(pub String);
+
+fn apply_log_filter(filter: Res<LogFilter>) {
+    if !filter.is_changed() || filter.is_added() {
+        return;
+    }
+    let Some(handle) = FILTER_HANDLE.get() else {
+        return;
+    };
+    match EnvFilter::try_new(&filter.0) {
+        Ok(new_filter) => match handle.reload(new_filter) {
+            Ok(()) => info!("log filter set to {}", filter.0),
+            Err(e) => error!("failed to reload log filter: {}", e),
+        },
+        Err(e) => warn!("invalid log filter {} ({})", filter.0, e),
+    }
+}
+
+#[cfg(feature = "dev")]
+fn console_log(world: &mut World, args: &[&str]) -> ConsoleResult {
+    if args.is_empty() {
+        return Ok(world.resource::<LogFilter>().0.clone());
+    }
+    let filter = args.join(",");
+    EnvFilter::try_new(&filter).map_err(|e| e.to_string())?;
+    world.resource_mut::<LogFilter>().0 = filter.clone();
+    Ok(format!("log filter {}", filter))
+}
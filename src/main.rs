@@ -2,6 +2,7 @@ use bevy::{asset, prelude::*, window::WindowResolution};
 use bevy_rapier2d::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
+#[cfg(feature = "dev")]
 use bevy_editor_pls::prelude::*;
 
 mod wall;
@@ -17,6 +18,24 @@ mod animate;
 mod bullet;
 mod comm;
 mod config;
+mod save;
+mod snapshot;
+mod pickup;
+mod spawner;
+mod boss;
+mod collision;
+mod camera;
+mod display;
+mod parallax;
+mod audio;
+mod hud;
+mod settings;
+mod locale;
+#[cfg(feature = "dev")]
+mod console;
+mod logging;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
 
 fn main() {
     let mut app = App::new();
@@ -25,11 +44,15 @@ fn main() {
     .add_plugins(DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
             title: "ASCEND".to_string(),
+            resolution: WindowResolution::new(
+                display::DEFAULT_RESOLUTION[0] as f32,
+                display::DEFAULT_RESOLUTION[1] as f32,
+            ),
             prevent_default_event_handling: false,
             ..default()
         }),
         ..default()
-    }))
+    }).set(logging::log_plugin()))
     .add_plugins(LdtkPlugin)
     .insert_resource(LdtkSettings {
         level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
@@ -40,7 +63,7 @@ fn main() {
     })
     .insert_resource(LevelSelection::Uid(0))
     .add_plugins((
-        // input::input_plugin,
+        input::input_plugin,
         player::player_plugin,
         wall::wall_plugin,
         global::global_plugin,
@@ -54,20 +77,42 @@ fn main() {
 
         RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(global::RAPIER_LENGTH_UNIT),
     ))
+    .add_plugins((
+        save::save_plugin,
+        snapshot::snapshot_plugin,
+        pickup::pickup_plugin,
+        spawner::spawner_plugin,
+        boss::boss_plugin,
+        collision::collision_plugin,
+        camera::camera_plugin,
+        display::display_plugin,
+        parallax::parallax_plugin,
+        audio::audio_plugin,
+        hud::hud_plugin,
+        settings::settings_plugin,
+        locale::locale_plugin,
+        logging::logging_plugin,
+    ))
     .add_systems(Startup, main_setup);
 
     app
         .init_state::<AppState>();
 
-    #[cfg(debug_assertions)]
+    #[cfg(feature = "dev")]
     {
         app.add_plugins((
             // LogDiagnosticsPlugin::default(),
             RapierDebugRenderPlugin::default(),
-            EditorPlugin::default()
+            EditorPlugin::default(),
+            console::console_plugin,
         ));
     }
 
+    #[cfg(feature = "diagnostics")]
+    {
+        app.add_plugins(diagnostics::diagnostics_plugin);
+    }
+
     app.run();
 }
 
@@ -86,7 +131,7 @@ pub fn main_setup(
     global_data: ResMut<global::GlobalData>,
     asset_server: Res<AssetServer>,
 ) {
-    cmds.spawn(Camera2dBundle::default()).insert(IsDefaultUiCamera);
+    cmds.spawn(Camera2dBundle::default()).insert((IsDefaultUiCamera, camera::CameraRig::default()));
     rapier_config.gravity = global_data.gravity;
 
 
@@ -0,0 +1,193 @@
+// 视差背景
+// 每个关卡通过 LDtk 自定义字段声明背景层, 下标相同的元素组成一层, 越靠前越远:
+// parallax_images: 图片路径, parallax_factors: 滚动系数, parallax_tile: 是否纵向平铺, parallax_scales: 缩放
+// 启动时先显示默认背景, 进入关卡后换成关卡声明的背景, 没有声明时仍为默认背景
+// 只有新关卡的背景层不同时才替换, 相同时保留原来的图层和原点, 跨关卡时背景不会跳动
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::ldtk::Level;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::camera::{apply_camera_rig, CameraRig};
+
+pub fn parallax_plugin(app: &mut App) {
+    app
+        .add_systems(Startup, setup_parallax)
+        .add_systems(
+            PostUpdate,
+            (spawn_level_parallax, scroll_parallax)
+                .chain()
+                .after(apply_camera_rig)
+                .before(TransformSystem::TransformPropagate),
+        );
+}
+
+const DEFAULT_BACKGROUND: &str = "images/bg.png";
+const DEFAULT_FACTOR: f32 = 0.2;
+const DEFAULT_SCALE: f32 = 0.4;
+// 最远一层的 z, 之后每层向前 LAYER_Z_STEP
+const PARALLAX_Z: f32 = -1.;
+const LAYER_Z_STEP: f32 = 0.01;
+// 平铺层在当前位置上下各多放一份, 保证覆盖整个视野
+const TILE_OFFSETS: [i32; 3] = [-1, 0, 1];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParallaxLayerInfo {
+    pub image: String,
+    // 0 表示固定在屏幕上, 1 表示和关卡一起移动
+    pub factor: f32,
+    pub tile: bool,
+    pub scale: f32,
+}
+
+impl Default for ParallaxLayerInfo {
+    fn default() -> Self {
+        ParallaxLayerInfo {
+            image: DEFAULT_BACKGROUND.to_string(),
+            factor: DEFAULT_FACTOR,
+            // 默认背景在所有关卡间保持不动, 需要纵向平铺才能一直覆盖视野
+            tile: true,
+            scale: DEFAULT_SCALE,
+        }
+    }
+}
+
+// 读取关卡的背景层, 缺少的系数使用默认值
+pub fn layers_from_level(level: &Level) -> Vec<ParallaxLayerInfo> {
+    let Ok(images) = level.get_strings_field("parallax_images") else {
+        return vec![ParallaxLayerInfo::default()];
+    };
+    let factors = level.get_floats_field("parallax_factors").ok();
+    let tiles = level.get_bools_field("parallax_tile").ok();
+    let scales = level.get_floats_field("parallax_scales").ok();
+    images
+        .iter()
+        .enumerate()
+        .filter_map(|(i, image)| {
+            let image = image.as_ref().filter(|s| !s.is_empty())?;
+            Some(ParallaxLayerInfo {
+                image: image.clone(),
+                factor: factors
+                    .and_then(|f| f.get(i).copied().flatten())
+                    .unwrap_or(DEFAULT_FACTOR),
+                tile: tiles.and_then(|t| t.get(i).copied()).unwrap_or(false),
+                scale: scales
+                    .and_then(|s| s.get(i).copied().flatten())
+                    .unwrap_or(DEFAULT_SCALE),
+            })
+        })
+        .collect()
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct ParallaxLayer {
+    pub factor: f32,
+    pub tile: bool,
+    // 相机在该点时图层位于原位, 取替换背景时所在关卡的中心
+    pub origin: Vec2,
+    // 平铺时相对当前位置偏移的份数
+    offset: i32,
+}
+
+fn spawn_layers(cmds: &mut Commands, asset_server: &AssetServer, layers: &[ParallaxLayerInfo], origin: Vec2) {
+    for (i, info) in layers.iter().enumerate() {
+        let texture: Handle<Image> = asset_server.load(info.image.clone());
+        let offsets: &[i32] = if info.tile { &TILE_OFFSETS } else { &[0] };
+        for offset in offsets {
+            cmds.spawn((
+                SpriteBundle {
+                    transform: Transform::from_translation(origin.extend(PARALLAX_Z + i as f32 * LAYER_Z_STEP))
+                        .with_scale(Vec3::new(info.scale, info.scale, 1.)),
+                    texture: texture.clone(),
+                    ..default()
+                },
+                ParallaxLayer {
+                    factor: info.factor,
+                    tile: info.tile,
+                    origin,
+                    offset: *offset,
+                },
+                Name::new(format!("parallax_{}_{}", i, offset)),
+            ));
+        }
+    }
+}
+
+// 还没有关卡时以世界原点为中心
+fn setup_parallax(mut cmds: Commands, asset_server: Res<AssetServer>) {
+    spawn_layers(&mut cmds, &asset_server, &[ParallaxLayerInfo::default()], Vec2::ZERO);
+}
+
+// 相机进入背景层不同的新关卡时替换背景层
+fn spawn_level_parallax(
+    mut cmds: Commands,
+    asset_server: Res<AssetServer>,
+    query_rig: Query<&CameraRig>,
+    query_layer: Query<Entity, With<ParallaxLayer>>,
+    ldtk_projects: Query<&Handle<LdtkProject>>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+    mut current: Local<Option<LevelIid>>,
+    mut shown: Local<Option<Vec<ParallaxLayerInfo>>>,
+) {
+    let Ok(rig) = query_rig.get_single() else {
+        return;
+    };
+    let (Some(level_iid), Some(bounds)) = (rig.level(), rig.bounds()) else {
+        return;
+    };
+    if current.as_ref() == Some(level_iid) {
+        return;
+    }
+    let Some(level) = ldtk_projects
+        .get_single()
+        .ok()
+        .and_then(|handle| ldtk_project_assets.get(handle))
+        .and_then(|project| project.get_raw_level_by_iid(&level_iid.to_string()))
+    else {
+        return;
+    };
+    *current = Some(level_iid.clone());
+
+    let layers = layers_from_level(level);
+    // 启动时显示的是默认背景
+    let shown = shown.get_or_insert_with(|| vec![ParallaxLayerInfo::default()]);
+    if *shown == layers {
+        return;
+    }
+    *shown = layers.clone();
+
+    for entity in query_layer.iter() {
+        cmds.entity(entity).despawn_recursive();
+    }
+    spawn_layers(&mut cmds, &asset_server, &layers, bounds.center());
+}
+
+fn scroll_parallax(
+    images: Res<Assets<Image>>,
+    query_camera: Query<&Transform, (With<CameraRig>, Without<ParallaxLayer>)>,
+    mut query_layer: Query<(&ParallaxLayer, &Handle<Image>, &mut Transform)>,
+) {
+    let Ok(camera_transform) = query_camera.get_single() else {
+        return;
+    };
+    let camera = camera_transform.translation.truncate();
+    for (layer, texture, mut transform) in query_layer.iter_mut() {
+        let mut position = layer.origin + (camera - layer.origin) * (1. - layer.factor);
+        // 平铺层按图片高度循环, 始终围绕相机
+        if layer.tile {
+            let height = images
+                .get(texture)
+                .map(|image| image.size_f32().y * transform.scale.y)
+                .unwrap_or(0.);
+            if height > 0. {
+                let cycles = ((camera.y - position.y) / height).round();
+                position.y += (cycles + layer.offset as f32) * height;
+            }
+        }
+        if transform.translation.truncate() != position {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+}
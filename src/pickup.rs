@@ -0,0 +1,230 @@
+// 场景中的道具拾取和宝箱
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::collision::{Layer, PlayerTouchedPickup};
+use crate::input::{Action, ActionInput};
+use crate::player::Player;
+use crate::roulette::{find_item_info_by_ldtk, Roulette, RouletteItemAddEvent, RouletteItemInfo};
+use crate::scene::Items;
+
+pub fn pickup_plugin(app: &mut App) {
+    app
+        .add_event::<ItemPickedUpEvent>()
+        .add_systems(Update, (
+            on_spawn_pickup,
+            bob_pickup,
+            collect_pickup,
+            open_chest,
+            animate_pickup_fx,
+        ));
+}
+
+const PICKUP_SIZE: f32 = 16.;
+const PICKUP_BOB_HEIGHT: f32 = 3.;
+const PICKUP_FX_TIME: f32 = 0.4;
+const CHEST_OPEN_RANGE: f32 = 24.;
+
+// 可拾取的道具, item 为 LDtk 中 Item 枚举的值
+// 字段缺少时 item 为空, 拾取时按未知物品忽略
+#[derive(Clone, Component, Debug, Default)]
+pub struct Pickup {
+    pub item: String,
+}
+
+impl From<&EntityInstance> for Pickup {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Pickup {
+            item: entity_instance
+                .get_enum_field("item")
+                .ok()
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct PickupBundle {
+    #[sprite_sheet_bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub pickup: Pickup,
+}
+
+#[derive(Clone, Component, Debug, Default)]
+pub struct Chest {
+    pub opened: bool,
+}
+
+#[derive(Event)]
+pub struct ItemPickedUpEvent {
+    pub id: u32,
+    pub position: Vec2,
+}
+
+// 上下浮动的基准高度
+#[derive(Component)]
+struct PickupBob {
+    origin_y: f32,
+}
+
+// 拾取后向上飘并淡出的特效
+#[derive(Component)]
+struct PickupFx {
+    timer: Timer,
+}
+
+fn on_spawn_pickup(
+    mut cmds: Commands,
+    query: Query<(Entity, &Transform), Added<Pickup>>,
+) {
+    for (entity, transform) in query.iter() {
+        cmds.entity(entity).insert((
+            Collider::ball(PICKUP_SIZE / 2.),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            Layer::Pickup.groups(),
+            PickupBob {
+                origin_y: transform.translation.y,
+            },
+        ));
+    }
+}
+
+fn bob_pickup(
+    time: Res<Time>,
+    mut query: Query<(&mut Transform, &PickupBob)>,
+) {
+    let offset = (time.elapsed_seconds() * 3.).sin() * PICKUP_BOB_HEIGHT;
+    for (mut transform, bob) in query.iter_mut() {
+        transform.translation.y = bob.origin_y + offset;
+    }
+}
+
+fn collect_pickup(
+    mut cmds: Commands,
+    mut events: EventReader<PlayerTouchedPickup>,
+    query_pickup: Query<(&Pickup, &GlobalTransform)>,
+    query_roulette: Query<&Roulette>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+    asset_server: Res<AssetServer>,
+    mut add_event: EventWriter<RouletteItemAddEvent>,
+    mut picked_event: EventWriter<ItemPickedUpEvent>,
+) {
+    let mut collected = HashSet::new();
+    for event in events.read() {
+        let pickup_entity = event.pickup;
+        let Ok((pickup, transform)) = query_pickup.get(pickup_entity) else {
+            continue;
+        };
+        if !collected.insert(pickup_entity) {
+            continue;
+        }
+        let Some(info) = find_item_info_by_ldtk(&item_infos, &pickup.item) else {
+            warn!("unknown pickup item {}", pickup.item);
+            continue;
+        };
+        // 轮盘满且有物品等待替换时, 道具留在原地
+        if query_roulette.iter().any(|roulette| !roulette.can_accept(info.id)) {
+            continue;
+        }
+
+        add_event.send(RouletteItemAddEvent { id: info.id });
+        picked_event.send(ItemPickedUpEvent {
+            id: info.id,
+            position: transform.translation().truncate(),
+        });
+
+        cmds.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+                    ..default()
+                },
+                texture: asset_server.load(info.texture.clone()),
+                transform: Transform::from_translation(transform.translation()),
+                ..default()
+            },
+            PickupFx {
+                timer: Timer::from_seconds(PICKUP_FX_TIME, TimerMode::Once),
+            },
+            Name::new("pickup_fx"),
+        ));
+        cmds.entity(pickup_entity).despawn_recursive();
+    }
+}
+
+fn animate_pickup_fx(
+    mut cmds: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut PickupFx, &mut Transform, &mut Sprite)>,
+) {
+    for (entity, mut fx, mut transform, mut sprite) in query.iter_mut() {
+        fx.timer.tick(time.delta());
+        let t = fx.timer.fraction();
+        transform.translation.y += 40. * time.delta_seconds();
+        transform.scale = Vec3::splat(1. + t * 0.5);
+        sprite.color.set_a(1. - t);
+        if fx.timer.finished() {
+            cmds.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// 玩家靠近宝箱按交互键打开, 掉落 LDtk 中配置的物品
+fn open_chest(
+    mut cmds: Commands,
+    input: ActionInput,
+    query_player: Query<&GlobalTransform, With<Player>>,
+    mut query_chest: Query<(&mut Chest, &Items, &Transform, &GlobalTransform, &mut Sprite, Option<&Parent>)>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !input.just_pressed(Action::Interact) {
+        return;
+    }
+    let Ok(player_transform) = query_player.get_single() else {
+        return;
+    };
+    for (mut chest, items, transform, global_transform, mut sprite, parent) in query_chest.iter_mut() {
+        if chest.opened
+            || global_transform.translation().truncate().distance(player_transform.translation().truncate()) > CHEST_OPEN_RANGE
+        {
+            continue;
+        }
+        chest.opened = true;
+        sprite.color = Color::GRAY;
+
+        let count = items.0.len() as f32;
+        for (i, item) in items.0.iter().enumerate() {
+            let Some(info) = find_item_info_by_ldtk(&item_infos, item) else {
+                warn!("unknown chest item {}", item);
+                continue;
+            };
+            let offset = Vec3::new((i as f32 - (count - 1.) / 2.) * PICKUP_SIZE, PICKUP_SIZE, 0.);
+            let pickup = cmds.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+                        ..default()
+                    },
+                    texture: asset_server.load(info.texture.clone()),
+                    transform: Transform::from_translation(transform.translation + offset),
+                    ..default()
+                },
+                Pickup {
+                    item: item.clone(),
+                },
+                Name::new("pickup"),
+            )).id();
+            // 和宝箱放在同一个关卡下, 关卡卸载时一起清理
+            if let Some(parent) = parent {
+                cmds.entity(parent.get()).add_child(pickup);
+            }
+        }
+    }
+}
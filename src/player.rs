@@ -1,32 +1,84 @@
-use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_rapier2d::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy::utils::HashSet;
 
+use crate::animate::{AnimEventKind, AnimationEvent};
+use crate::audio::{PlaySfxEvent, Sfx};
 use crate::bullet::BulletEvent;
-use crate::roulette::RouletteRotateEvent;
+use crate::comm::{Facing, Health};
+#[cfg(feature = "dev")]
+use crate::console::{console_arg, AddConsoleCommand, ConsoleResult};
+use crate::display::cursor_to_world;
+use crate::input::{Action, ActionInput};
+use crate::roulette::{RouletteRotateEvent, RouletteSpinEvent};
 
 use crate::scene::Climbable;
+use crate::collision::{ClimberLeftLadder, ClimberTouchedLadder, Layer};
 use crate::scene::ColliderBundle;
 use crate::scene::GroundSensor;
 use crate::scene::Items;
-use crate::animate::PlayerAnimateEvent;
+use crate::AppState;
 
 pub fn player_plugin(app: &mut App) {
     app
+    .add_event::<PlayerDamageEvent>()
     .add_systems(Startup, (
         spawn_player,
     ))
     .add_systems(Update, on_spawn_player)
     .add_systems(Update, player_move)
-    .add_systems(Update, (detect_climb_range, ignore_gravity_if_climbing, cursor_move));
+    .add_systems(Update, (detect_climb_range, ignore_gravity_if_climbing, cursor_move))
+    .add_systems(Update, (on_player_damage, on_player_death_finished));
+
+    #[cfg(feature = "dev")]
+    {
+        app
+            .add_systems(Update, noclip_move)
+            .add_console_command("tp", "tp <x> <y>: 把玩家移动到世界坐标", console_tp)
+            .add_console_command("god", "god: 切换无敌", console_god)
+            .add_console_command("noclip", "noclip: 切换穿墙飞行", console_noclip);
+    }
 }
 
+const PLAYER_HEALTH: f32 = 5.;
+// 每帧水平移动的像素
+pub const PLAYER_MOVE_STEP: f32 = 2.;
+// 穿墙时每秒移动的像素
+#[cfg(feature = "dev")]
+const NOCLIP_SPEED: f32 = 600.;
+
 #[derive(Component, Clone, Default)]
 pub struct Player;
 
+// 不受伤害, 目前只由控制台的 god 命令添加
+#[derive(Component, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "dev"), allow(dead_code))]
+pub struct Invulnerable;
+
+// 不受碰撞和重力影响, 直接按方向键移动, 只由控制台的 noclip 命令添加
+#[derive(Component, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "dev"), allow(dead_code))]
+pub struct Noclip;
+
+// 跳跃和下落的状态, 放在组件上以便快照恢复
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerMotion {
+    // 每帧竖直移动的像素, 受重力影响
+    pub vertical: f32,
+    // 离开地面后仍可起跳的剩余时间
+    pub grounded_timer: f32,
+}
+
+// 玩家受到伤害, source 为造成伤害的敌人或子弹
+#[derive(Event)]
+pub struct PlayerDamageEvent {
+    pub source: Entity,
+    pub target: Entity,
+    pub damage: f32,
+}
+
 pub fn on_spawn_player(mut commands: Commands, mut players: Query<(Entity), Added<Player>>) {
     for player_entity in players.iter_mut() {
         commands
@@ -42,7 +94,38 @@ pub fn on_spawn_player(mut commands: Commands, mut players: Query<(Entity), Adde
                 offset: CharacterLength::Absolute(0.01),
                 ..default()
             })
-            .insert(ActiveCollisionTypes::all());
+            .insert(ActiveCollisionTypes::all())
+            .insert(Health::new(PLAYER_HEALTH))
+            .insert(PlayerMotion::default())
+            .insert(Facing::default());
+    }
+}
+
+fn on_player_damage(
+    mut events: EventReader<PlayerDamageEvent>,
+    mut query: Query<&mut Health, (With<Player>, Without<Invulnerable>)>,
+) {
+    for event in events.read() {
+        if let Ok(mut health) = query.get_mut(event.target) {
+            if health.damage(event.damage) {
+                info!("player killed by {:?}", event.source);
+            } else {
+                debug!("player took {} damage from {:?}", event.damage, event.source);
+            }
+        }
+    }
+}
+
+// 死亡动画播放完后进入 GameOver
+fn on_player_death_finished(
+    mut events: EventReader<AnimationEvent>,
+    query: Query<(), With<Player>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for event in events.read() {
+        if event.kind == AnimEventKind::DeathFinished && query.contains(event.entity) {
+            next_state.set(AppState::GameOver);
+        }
     }
 }
 #[derive(Clone, Default, Bundle, LdtkEntity)]
@@ -80,10 +163,7 @@ fn cursor_move(
     mut gizmos: Gizmos,
 ) {
     let (camera, camera_transform) = camera_query.single();
-    let Some(cursor_position) = windows.single().cursor_position() else {
-        return;
-    };
-    let Some(point) = camera.viewport_to_world_2d(camera_transform, cursor_position) else {
+    let Some(point) = cursor_to_world(windows.single(), camera, camera_transform) else {
         return;
     };
     gizmos.circle_2d(point, 10., Color::WHITE);
@@ -105,7 +185,7 @@ fn spawn_player(
             collider: Collider::cuboid(36.75, 130.),
             rotation_constraints: LockedAxes::ROTATION_LOCKED,
             ..default()
-        },
+        }.with_layer(Layer::Player),
         Climber::default(),
         Name::new("player"),
     ));
@@ -113,99 +193,107 @@ fn spawn_player(
 
 fn player_move(
     mut cmds: Commands,
-    input: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
     time: Res<Time>,
     mut query: Query<
         (
+            Entity,
             &Transform,
             &mut Velocity,
             &mut Climber,
             &GroundSensor,
             &mut KinematicCharacterController,
             Option<&KinematicCharacterControllerOutput>,
+            Option<&Health>,
+            &mut Facing,
+            &mut PlayerMotion,
         ),
-        With<Player>,
+        (With<Player>, Without<Noclip>),
     >,
     climbables: Query<Entity, With<Climbable>>,
-    mut mouse_wheel_events: EventReader<MouseWheel>,
-    input_mouse_button: Res<ButtonInput<MouseButton>>,
-    mut vertical_movement: Local<f32>,
     mut roulette_event: EventWriter<RouletteRotateEvent>,
     camera_query: Query<(&Camera, &GlobalTransform), With<IsDefaultUiCamera>>,
     windows: Query<&Window>,
-    mut animate_event: EventWriter<PlayerAnimateEvent>,
-    mut grounded_timer: Local<f32>,
     mut bullet_event: EventWriter<BulletEvent>,
+    mut spin_event: EventWriter<RouletteSpinEvent>,
+    mut sfx_event: EventWriter<PlaySfxEvent>,
 ) {
     let delta_time = time.delta_seconds();
-    for (transform, mut velocity, mut climber, ground_detection, mut controller, output) in &mut query {
-        let right = if input.pressed(KeyCode::KeyD) { 1. } else { 0. };
-        let left = if input.pressed(KeyCode::KeyA) { 1. } else { 0. };
+    for (entity, transform, mut velocity, mut climber, ground_detection, mut controller, output, health, mut facing, mut motion) in &mut query {
+        if health.is_some_and(|h| h.is_dead()) {
+            continue;
+        }
+        let right = if input.pressed(Action::RightMove) { 1. } else { 0. };
+        let left = if input.pressed(Action::LeftMove) { 1. } else { 0. };
         let mut movement = Vec2::ZERO;
-        movement.x = (right - left) * 2.;
-        if right - left != 0.0 {
-            animate_event.send(PlayerAnimateEvent::Walk(Vec2::new(right-left, 0.0)));
+        movement.x = (right - left) * PLAYER_MOVE_STEP;
+        if let Some(new_facing) = Facing::from_x(right - left) {
+            facing.set_if_neq(new_facing);
         }
         if output.map(|o| o.grounded).unwrap_or(false) {
-            *grounded_timer = 0.5;
-            *vertical_movement = 0.0;
+            motion.grounded_timer = 0.5;
+            motion.vertical = 0.0;
         }
         
         if climber.intersecting_climbables.is_empty() {
             climber.climbing = false;
-        } else if input.just_pressed(KeyCode::KeyW) || input.just_pressed(KeyCode::KeyS) {
+        } else if input.just_pressed(Action::ClimbUp) || input.just_pressed(Action::ClimbDown) {
             climber.climbing = true;
         }
 
         if climber.climbing {
-            let up = if input.pressed(KeyCode::KeyW) { 1. } else { 0. };
-            let down = if input.pressed(KeyCode::KeyS) { 1. } else { 0. };
+            let up = if input.pressed(Action::ClimbUp) { 1. } else { 0. };
+            let down = if input.pressed(Action::ClimbDown) { 1. } else { 0. };
             movement.y = 2.0;
-            *vertical_movement = (up - down) * 2.;
+            motion.vertical = (up - down) * 2.;
         }
 
-        if input.just_pressed(KeyCode::Space) {
+        if input.just_pressed(Action::Jump) {
             movement.y = 3.;
             climber.climbing = false;
         }
         let jump_speed = movement.y;
         // If we are grounded we can jump
-        if *grounded_timer > 0.0 {
-            *grounded_timer -= delta_time;
+        if motion.grounded_timer > 0.0 {
+            motion.grounded_timer -= delta_time;
             // If we jump we clear the grounded tolerance
             if jump_speed > 0.0 {
-                *vertical_movement = jump_speed;
-                *grounded_timer = 0.0;
+                motion.vertical = jump_speed;
+                motion.grounded_timer = 0.0;
+                if !climber.climbing {
+                    sfx_event.send(PlaySfxEvent::at(Sfx::Jump, transform.translation.truncate()));
+                }
             }
         }
-        movement.y = *vertical_movement;
+        movement.y = motion.vertical;
         if (!climber.climbing) {
-            *vertical_movement += -9.81 * delta_time;
+            motion.vertical += -9.81 * delta_time;
         }
         controller.translation = Some(movement);
 
-        if input_mouse_button.just_pressed(MouseButton::Left) {
-            if let Some(cursor_position) = windows.single().cursor_position() {
-                for (camera, camera_transform) in camera_query.iter() {
-                    if let Some(point) = camera.viewport_to_world_2d(camera_transform, cursor_position) {
-                        let direction = get_mouse_direction(transform, point);
-                        let vel = direction * 1500.;
-                        bullet_event.send(BulletEvent { transform: Transform::from_xyz(transform.translation.x + direction.x * 100.0, transform.translation.y + direction.y * 100.0, transform.translation.z), vel});
+        if input.just_pressed(Action::LeftShoot) {
+            let window = windows.single();
+            for (camera, camera_transform) in camera_query.iter() {
+                if let Some(point) = cursor_to_world(window, camera, camera_transform) {
+                    let direction = get_mouse_direction(transform, point);
+                    // 瞄准方向决定朝向, 子弹从朝向一侧生成
+                    if let Some(new_facing) = Facing::from_x(direction.x) {
+                        facing.set_if_neq(new_facing);
                     }
+                    bullet_event.send(BulletEvent { shooter: entity, direction });
                 }
             }
         }
 
-        for event in mouse_wheel_events.read() {
-            match event.y {
-                1.0 => {
-                    roulette_event.send(RouletteRotateEvent::Left);
-                }
-                -1.0 => {
-                    roulette_event.send(RouletteRotateEvent::Right);
-                }
-                _ => {}
-            }
+        if input.just_pressed(Action::Spin) {
+            spin_event.send(RouletteSpinEvent);
+        }
+
+        if input.just_pressed(Action::RotateLeft) {
+            roulette_event.send(RouletteRotateEvent::Left);
+        }
+        if input.just_pressed(Action::RotateRight) {
+            roulette_event.send(RouletteRotateEvent::Right);
         }
 
     }
@@ -220,36 +308,17 @@ fn get_mouse_direction(transform: &Transform, cursor_position: Vec2) -> Vec2 {
 
 pub fn detect_climb_range(
     mut climbers: Query<&mut Climber>,
-    climbables: Query<Entity, With<Climbable>>,
-    mut collisions: EventReader<CollisionEvent>,
+    mut touched_events: EventReader<ClimberTouchedLadder>,
+    mut left_events: EventReader<ClimberLeftLadder>,
 ) {
-    for collision in collisions.read() {
-        match collision {
-            CollisionEvent::Started(collider_a, collider_b, _) => {
-                if let (Ok(mut climber), Ok(climbable)) =
-                    (climbers.get_mut(*collider_a), climbables.get(*collider_b))
-                {
-                    climber.intersecting_climbables.insert(climbable);
-                }
-                if let (Ok(mut climber), Ok(climbable)) =
-                    (climbers.get_mut(*collider_b), climbables.get(*collider_a))
-                {
-                    climber.intersecting_climbables.insert(climbable);
-                };
-            }
-            CollisionEvent::Stopped(collider_a, collider_b, _) => {
-                if let (Ok(mut climber), Ok(climbable)) =
-                    (climbers.get_mut(*collider_a), climbables.get(*collider_b))
-                {
-                    climber.intersecting_climbables.remove(&climbable);
-                }
-
-                if let (Ok(mut climber), Ok(climbable)) =
-                    (climbers.get_mut(*collider_b), climbables.get(*collider_a))
-                {
-                    climber.intersecting_climbables.remove(&climbable);
-                }
-            }
+    for event in touched_events.read() {
+        if let Ok(mut climber) = climbers.get_mut(event.climber) {
+            climber.intersecting_climbables.insert(event.ladder);
+        }
+    }
+    for event in left_events.read() {
+        if let Ok(mut climber) = climbers.get_mut(event.climber) {
+            climber.intersecting_climbables.remove(&event.ladder);
         }
     }
 }
@@ -277,3 +346,87 @@ pub fn ignore_gravity_if_climbing(
         }
     }
 }
+
+#[cfg(feature = "dev")]
+fn noclip_move(
+    input: ActionInput,
+    time: Res<Time>,
+    mut query: Query<&mut Transform, (With<Player>, With<Noclip>)>,
+) {
+    let axis = |positive: Action, negative: Action| {
+        (input.pressed(positive) as i32 - input.pressed(negative) as i32) as f32
+    };
+    let direction = Vec2::new(
+        axis(Action::RightMove, Action::LeftMove),
+        axis(Action::ClimbUp, Action::ClimbDown),
+    );
+    if direction == Vec2::ZERO {
+        return;
+    }
+    for mut transform in query.iter_mut() {
+        transform.translation += (direction * NOCLIP_SPEED * time.delta_seconds()).extend(0.);
+    }
+}
+
+#[cfg(feature = "dev")]
+fn console_tp(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let x: f32 = console_arg(args, 0, "x")?;
+    let y: f32 = console_arg(args, 1, "y")?;
+    teleport_player(world, Vec2::new(x, y))?;
+    Ok(format!("teleported to {} {}", x, y))
+}
+
+// 供其他模块的命令使用, 同时清掉速度
+#[cfg(feature = "dev")]
+pub fn teleport_player(world: &mut World, position: Vec2) -> Result<(), String> {
+    let mut query = world.query_filtered::<(&mut Transform, Option<&mut Velocity>), With<Player>>();
+    let (mut transform, velocity) = query.iter_mut(world).next().ok_or("no player")?;
+    transform.translation = position.extend(transform.translation.z);
+    if let Some(mut velocity) = velocity {
+        *velocity = Velocity::zero();
+    }
+    Ok(())
+}
+
+#[cfg(feature = "dev")]
+fn player_entity(world: &mut World) -> Result<Entity, String> {
+    world
+        .query_filtered::<Entity, With<Player>>()
+        .iter(world)
+        .next()
+        .ok_or_else(|| "no player".to_string())
+}
+
+// 有 T 时移除, 没有时加上, 返回是否开启
+#[cfg(feature = "dev")]
+fn toggle_player_cheat<T: Component + Default>(world: &mut World) -> Result<bool, String> {
+    let player = player_entity(world)?;
+    let mut entity = world.entity_mut(player);
+    let enabled = !entity.contains::<T>();
+    if enabled {
+        entity.insert(T::default());
+    } else {
+        entity.remove::<T>();
+    }
+    Ok(enabled)
+}
+
+#[cfg(feature = "dev")]
+fn console_god(world: &mut World, _args: &[&str]) -> ConsoleResult {
+    let enabled = toggle_player_cheat::<Invulnerable>(world)?;
+    Ok(format!("god {}", if enabled { "on" } else { "off" }))
+}
+
+// 同时关闭碰撞体, 不会被敌人和子弹打中
+#[cfg(feature = "dev")]
+fn console_noclip(world: &mut World, _args: &[&str]) -> ConsoleResult {
+    let enabled = toggle_player_cheat::<Noclip>(world)?;
+    let player = player_entity(world)?;
+    let mut entity = world.entity_mut(player);
+    if enabled {
+        entity.insert(ColliderDisabled);
+    } else {
+        entity.remove::<ColliderDisabled>();
+    }
+    Ok(format!("noclip {}", if enabled { "on" } else { "off" }))
+}
@@ -1,18 +1,30 @@
 // 道具轮盘
 
+use std::f32::consts::{PI, TAU};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use bevy::ecs::event;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::comm::Facing;
+#[cfg(feature = "dev")]
+use crate::console::{console_arg, AddConsoleCommand, ConsoleResult};
+use crate::input::{Action, ActionInput};
+use crate::locale::Locale;
 use crate::player::Player;
-use crate::global::GlobalData;
 
 pub fn roulette_plugin(app: &mut App) {
     app
         .register_type::<Roulette>()
         .add_event::<RouletteRotateEvent>()
         .add_event::<RouletteItemAddEvent>()
-        .add_event::<ShowItemEvent>()
+        .add_event::<RouletteItemRemoveEvent>()
+        .add_event::<RouletteConsumeEvent>()
+        .add_event::<RouletteChangedEvent>()
+        .add_event::<RouletteSpinEvent>()
+        .add_event::<RouletteSpinSettledEvent>()
+        .insert_resource(RouletteRng::from_env())
         .add_systems(Startup, (
             setup,
         ))
@@ -22,18 +34,33 @@ pub fn roulette_plugin(app: &mut App) {
         .add_systems(Update, (
             // update_roulette,
             on_add_item,
-            timer_hide_item,
-            show_all_item,
-
-            test_add_item,
-            test_rotate,
+            on_remove_item,
+            on_consume_item,
+            on_spin,
+            tick_spin,
+            choose_swap,
+            rebuild_wheel,
+            face_wheel_items,
+            rotate_wheel,
+            fade_wheel,
+            update_item_name,
+            log_roulette_changes,
         ));
+
+    #[cfg(feature = "dev")]
+    {
+        app
+            .add_systems(Update, (test_add_item, test_rotate))
+            .add_console_command("give", "give <item_id> [count]: 把物品加入轮盘", console_give)
+            .add_console_command("take", "take <item_id>: 从轮盘移除物品", console_take);
+    }
 }
 
-// 轮盘物品
+// 轮盘物品的 UI 节点, index 为在轮盘中的位置
 #[derive(Component)]
 pub struct RouletteItem {
     pub id: u32,
+    pub index: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug, TypePath, Asset, Clone)]
@@ -43,23 +70,187 @@ pub struct RouletteItemInfo {
     pub texture: String,
     pub width: f32,
     pub height: f32,
+    // 每个物品可使用的次数, 0 表示无限
+    #[serde(default)]
+    pub charges: u32,
+    // 转盘抽中的权重
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    // 对应 LDtk 中 Item 枚举的值
+    #[serde(default)]
+    pub ldtk: String,
+}
+
+fn default_weight() -> f32 {
+    1.
+}
+
+pub fn find_item_info(item_infos: &Assets<RouletteItemInfo>, id: u32) -> Option<&RouletteItemInfo> {
+    item_infos.iter().map(|(_, info)| info).find(|info| info.id == id)
+}
+
+pub fn find_item_info_by_ldtk<'a>(item_infos: &'a Assets<RouletteItemInfo>, ldtk: &str) -> Option<&'a RouletteItemInfo> {
+    item_infos.iter().map(|(_, info)| info).find(|info| info.ldtk == ldtk)
+}
+
+pub const ROULETTE_CAPACITY: usize = 6;
+
+// 轮盘上的一格, 相同 id 的物品叠加在同一格
+#[derive(Serialize, Deserialize, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouletteSlot {
+    pub id: u32,
+    pub count: u32,
+    // 当前这一个物品剩余的次数
+    pub charges: u32,
+    pub max_charges: u32,
+}
+
+impl RouletteSlot {
+    pub fn new(id: u32, max_charges: u32) -> Self {
+        Self {
+            id,
+            count: 1,
+            charges: max_charges,
+            max_charges,
+        }
+    }
+}
+
+pub const SPIN_LAPS: usize = 2;
+// 转动时每一格的基础停留时间, 越接近结果越慢
+pub const SPIN_STEP_TIME: f32 = 0.05;
+pub const SPIN_COOLDOWN: f32 = 3.;
+
+#[derive(Reflect, Debug, Clone)]
+pub struct Spin {
+    steps_left: usize,
+    total_steps: usize,
+    timer: Timer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinTick {
+    Stepped(usize),
+    Settled(usize),
+}
+
+// 按权重选出一个下标, r 取值 [0, 1)
+pub fn pick_weighted(weights: &[f32], r: f32) -> Option<usize> {
+    let total: f32 = weights.iter().filter(|w| **w > 0.).sum();
+    if total <= 0. {
+        return None;
+    }
+    let mut x = r * total;
+    let mut last = None;
+    for (i, w) in weights.iter().enumerate() {
+        if *w <= 0. {
+            continue;
+        }
+        if x < *w {
+            return Some(i);
+        }
+        x -= w;
+        last = Some(i);
+    }
+    last
+}
+
+const SEED_ENV_VAR: &str = "ASCEND_SEED";
+
+// 十进制或 0x 开头的十六进制
+fn parse_seed(value: Option<&str>) -> Option<u64> {
+    let value = value?.trim();
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// 可设定种子的随机数 (SplitMix64), 同一个种子得到同样的转盘结果, 便于回放
+#[derive(Resource, Debug, Clone)]
+pub struct RouletteRng {
+    pub seed: u64,
+    state: u64,
+}
+
+impl RouletteRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+        }
+    }
+
+    pub fn from_entropy() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(seed)
+    }
+
+    // 环境变量 ASCEND_SEED 指定种子, 用于复现某一局; 没有或格式错误时用时间
+    pub fn from_env() -> Self {
+        let var = std::env::var(SEED_ENV_VAR).ok();
+        let rng = match parse_seed(var.as_deref()) {
+            Some(seed) => Self::new(seed),
+            None => {
+                if let Some(var) = &var {
+                    warn!("invalid {} {:?}, using a random seed", SEED_ENV_VAR, var);
+                }
+                Self::from_entropy()
+            }
+        };
+        info!("roulette seed {}", rng.seed);
+        rng
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouletteAdd {
+    Added(usize),
+    Stacked(usize),
+    // 轮盘已满, 物品暂存在 pending 等待玩家选择替换
+    Full,
+    // 轮盘已满且已有暂存物品, 不接收新物品
+    Rejected,
 }
 
 // 轮盘
 #[derive(Component, Reflect)]
 pub struct Roulette {
-    list: Vec<Entity>,
+    list: Vec<RouletteSlot>,
     cur_index: usize,
+    capacity: usize,
+    pending: Option<RouletteSlot>,
+    spin: Option<Spin>,
+    spin_cooldown: Timer,
     timer: Timer,
 }
 
 impl Default for Roulette {
     fn default() -> Self {
-        Self {
-            list: vec![],
-            cur_index: 0,
-            timer: Timer::from_seconds(2., TimerMode::Repeating),
-        }
+        Self::with_capacity(ROULETTE_CAPACITY)
     }
 }
 
@@ -68,7 +259,22 @@ impl Roulette {
         Self::default()
     }
 
-    pub fn get_all_items(&self) -> &Vec<Entity> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        // 冷却一开始就是结束状态
+        let mut spin_cooldown = Timer::from_seconds(SPIN_COOLDOWN, TimerMode::Once);
+        spin_cooldown.tick(spin_cooldown.duration());
+        Self {
+            list: vec![],
+            cur_index: 0,
+            capacity,
+            pending: None,
+            spin: None,
+            spin_cooldown,
+            timer: Timer::from_seconds(2., TimerMode::Once),
+        }
+    }
+
+    pub fn get_all_items(&self) -> &Vec<RouletteSlot> {
         &self.list
     }
 
@@ -76,11 +282,169 @@ impl Roulette {
         self.list.len()
     }
 
-    pub fn add_item(&mut self, item: Entity) {
-        self.list.push(item);
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
     }
 
-    pub fn get_item(&self, index: usize) -> Option<&Entity> {
+    pub fn is_full(&self) -> bool {
+        self.list.len() >= self.capacity
+    }
+
+    pub fn find(&self, id: u32) -> Option<usize> {
+        self.list.iter().position(|slot| slot.id == id)
+    }
+
+    // 能否放入物品: 已有的物品可以叠加, 轮盘满时只能暂存一个
+    pub fn can_accept(&self, id: u32) -> bool {
+        self.find(id).is_some() || !self.is_full() || self.pending.is_none()
+    }
+
+    pub fn add_item(&mut self, id: u32, max_charges: u32) -> RouletteAdd {
+        if let Some(index) = self.find(id) {
+            self.list[index].count += 1;
+            return RouletteAdd::Stacked(index);
+        }
+        if self.is_full() {
+            if self.pending.is_some() {
+                return RouletteAdd::Rejected;
+            }
+            self.pending = Some(RouletteSlot::new(id, max_charges));
+            return RouletteAdd::Full;
+        }
+        self.list.push(RouletteSlot::new(id, max_charges));
+        RouletteAdd::Added(self.list.len() - 1)
+    }
+
+    pub fn remove_item(&mut self, index: usize) -> Option<RouletteSlot> {
+        if index >= self.list.len() {
+            return None;
+        }
+        let slot = self.list.remove(index);
+        if index < self.cur_index || self.cur_index >= self.list.len() {
+            self.cur_index = self.cur_index.saturating_sub(1);
+        }
+        Some(slot)
+    }
+
+    pub fn remove_id(&mut self, id: u32) -> Option<RouletteSlot> {
+        self.find(id).and_then(|index| self.remove_item(index))
+    }
+
+    // 消耗当前物品的一次使用次数, 用完后叠加数量减一, 数量为零时移除
+    // 返回被消耗的物品 id
+    pub fn consume(&mut self) -> Option<u32> {
+        let index = self.cur_index;
+        let slot = self.list.get_mut(index)?;
+        let id = slot.id;
+        if slot.max_charges == 0 {
+            return Some(id);
+        }
+        slot.charges = slot.charges.saturating_sub(1);
+        if slot.charges == 0 {
+            slot.count -= 1;
+            slot.charges = slot.max_charges;
+            if slot.count == 0 {
+                self.remove_item(index);
+            }
+        }
+        Some(id)
+    }
+
+    pub fn get_pending(&self) -> Option<&RouletteSlot> {
+        self.pending.as_ref()
+    }
+
+    // 用暂存的物品替换当前物品, 返回被换下的物品
+    pub fn swap_pending(&mut self) -> Option<RouletteSlot> {
+        let pending = self.pending.take()?;
+        match self.list.get_mut(self.cur_index) {
+            Some(slot) => Some(std::mem::replace(slot, pending)),
+            None => {
+                self.list.push(pending);
+                None
+            }
+        }
+    }
+
+    pub fn discard_pending(&mut self) -> Option<RouletteSlot> {
+        self.pending.take()
+    }
+
+    // 直接替换全部物品, 用于快照恢复
+    // 正在进行的转动直接取消, 不会在恢复后停下并触发结果
+    // 快照中的次数可能超出范围, 限制在 1..=max_charges
+    pub fn load(&mut self, mut list: Vec<RouletteSlot>, cur_index: usize, mut pending: Option<RouletteSlot>) {
+        for slot in list.iter_mut().chain(pending.iter_mut()).filter(|slot| slot.max_charges > 0) {
+            slot.charges = slot.charges.clamp(1, slot.max_charges);
+        }
+        self.list = list;
+        self.pending = pending;
+        self.cur_index = cur_index.min(self.list.len().saturating_sub(1));
+        self.spin = None;
+    }
+
+    pub fn is_spinning(&self) -> bool {
+        self.spin.is_some()
+    }
+
+    pub fn can_spin(&self) -> bool {
+        !self.list.is_empty() && self.spin.is_none() && self.spin_cooldown.finished()
+    }
+
+    // 开始转动, 转过 laps 圈后停在 target
+    pub fn start_spin(&mut self, target: usize, laps: usize) -> bool {
+        let len = self.list.len();
+        if !self.can_spin() || target >= len {
+            return false;
+        }
+        let mut steps = laps * len + (target + len - self.cur_index) % len;
+        if steps == 0 {
+            steps = len;
+        }
+        self.spin = Some(Spin {
+            steps_left: steps,
+            total_steps: steps,
+            timer: Timer::from_seconds(SPIN_STEP_TIME, TimerMode::Once),
+        });
+        true
+    }
+
+    // 推进转动, 每次最多走一格
+    pub fn tick_spin(&mut self, delta: Duration) -> Option<SpinTick> {
+        self.spin_cooldown.tick(delta);
+        let len = self.list.len();
+        let spin = self.spin.as_mut()?;
+        if len == 0 {
+            self.spin = None;
+            return None;
+        }
+        spin.timer.tick(delta);
+        if !spin.timer.finished() {
+            return None;
+        }
+
+        self.cur_index = (self.cur_index + 1) % len;
+        spin.steps_left -= 1;
+        if spin.steps_left == 0 {
+            self.spin = None;
+            self.spin_cooldown.reset();
+            return Some(SpinTick::Settled(self.cur_index));
+        }
+        let progress = 1. - spin.steps_left as f32 / spin.total_steps as f32;
+        spin.timer = Timer::from_seconds(SPIN_STEP_TIME * (1. + 4. * progress * progress), TimerMode::Once);
+        Some(SpinTick::Stepped(self.cur_index))
+    }
+
+    // 轮盘有操作时重新显示
+    pub fn wake(&mut self) {
+        self.timer.reset();
+    }
+
+    pub fn get_item(&self, index: usize) -> Option<&RouletteSlot> {
         self.list.get(index)
     }
 
@@ -88,15 +452,21 @@ impl Roulette {
         self.cur_index
     }
 
-    pub fn get_cur_item(&self) -> Option<&Entity> {
+    pub fn get_cur_item(&self) -> Option<&RouletteSlot> {
         self.list.get(self.cur_index)
     }
 
-    pub fn get_prev_item(&self) -> Option<&Entity> {
+    pub fn get_prev_item(&self) -> Option<&RouletteSlot> {
+        if self.list.is_empty() {
+            return None;
+        }
         self.list.get((self.cur_index + self.list.len() - 1) % self.list.len())
     }
 
-    pub fn get_next_item(&self) -> Option<&Entity> {
+    pub fn get_next_item(&self) -> Option<&RouletteSlot> {
+        if self.list.is_empty() {
+            return None;
+        }
         self.list.get((self.cur_index + 1) % self.list.len())
     }
 
@@ -124,8 +494,71 @@ fn setup(
         roulette,
         Name::new("Roulette")
     ));
+
+    // 屏幕下方的轮盘 UI, 物品作为子节点围成一圈
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(WHEEL_SIZE),
+                height: Val::Px(WHEEL_SIZE),
+                left: Val::Percent(50.),
+                bottom: Val::Px(20.),
+                margin: UiRect::left(Val::Px(-WHEEL_SIZE / 2.)),
+                ..default()
+            },
+            ..default()
+        },
+        RouletteWheel::default(),
+        Name::new("RouletteWheel"),
+    )).with_children(|parent| {
+        parent.spawn((
+            TextBundle {
+                text: Text::from_section("", TextStyle {
+                    font_size: 24.,
+                    color: Color::BLACK,
+                    ..default()
+                }).with_justify(JustifyText::Center),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    top: Val::Px(WHEEL_SIZE / 2. - 12.),
+                    ..default()
+                },
+                ..default()
+            },
+            RouletteItemName,
+        ));
+    });
+}
+
+const WHEEL_SIZE: f32 = 240.;
+const WHEEL_RADIUS: f32 = 80.;
+const ITEM_SIZE: f32 = 40.;
+const ITEM_HIGHLIGHT_SCALE: f32 = 1.5;
+// 转动时每秒逼近目标角度的速度
+const WHEEL_ROTATE_SPEED: f32 = 12.;
+const WHEEL_FADE_TIME: f32 = 0.5;
+
+#[derive(Component)]
+pub struct RouletteWheel {
+    // 当前转过的角度, 逐帧缓动到 cur_index 对应的角度
+    angle: f32,
+    alpha: f32,
 }
 
+impl Default for RouletteWheel {
+    fn default() -> Self {
+        Self {
+            angle: 0.,
+            alpha: 1.,
+        }
+    }
+}
+
+#[derive(Component)]
+struct RouletteItemName;
+
 #[derive(Event)]
 pub enum RouletteRotateEvent {
     Left,
@@ -136,13 +569,14 @@ pub enum RouletteRotateEvent {
 // 用法：触发RouletteEvent事件
 pub fn roulette_event(
     mut roulette_events: EventReader<RouletteRotateEvent>,
-    mut query_roulette_item: Query<(&mut Transform, &mut RouletteItem)>,
     mut query: Query<&mut Roulette>,
-    global_data: Res<GlobalData>,
-    mut show_event: EventWriter<ShowItemEvent>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
 ) {
     for event in roulette_events.read() {
         for mut roulette in query.iter_mut() {
+            if roulette.is_spinning() {
+                continue;
+            }
             match event {
                 RouletteRotateEvent::Left => {
                     roulette.prev();
@@ -151,23 +585,13 @@ pub fn roulette_event(
                     roulette.next();
                 }
             }
+            roulette.wake();
+            changed_event.send(RouletteChangedEvent::Rotated(roulette.get_cur_index()));
         }
-        
-        let mut roulette = query.single_mut();
-        roulette.timer.reset();
-        println!("cur_index: {} {}", roulette.get_cur_index(), roulette.len());
-        let cur_index = roulette.get_cur_index();
-        for i in 0..roulette.len() {
-            let item_id = roulette.get_item(i).unwrap();
-            if let Ok((mut transform, mut item)) = query_roulette_item.get_mut(*item_id) {
-                // println!("======, {} {}", i, cur_index);
-                transform.translation.x = (i as f32 - cur_index as f32) * 50. * global_data.scale;
-            }
-        }
-        show_event.send(ShowItemEvent::Three);
     }
 }
 
+#[cfg(feature = "dev")]
 pub fn test_rotate(
     mut events: EventWriter<RouletteRotateEvent>,
     input: Res<ButtonInput<KeyCode>>,
@@ -185,50 +609,257 @@ pub struct RouletteItemAddEvent {
     pub id: u32,
 }
 
+#[derive(Event)]
+pub struct RouletteItemRemoveEvent {
+    pub id: u32,
+}
+
+// 消耗当前物品的一次使用次数
+#[derive(Event)]
+pub struct RouletteConsumeEvent;
+
+// 轮盘内容或当前物品变化后发出
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouletteChangedEvent {
+    Added(u32),
+    Stacked(u32),
+    Removed(u32),
+    Consumed(u32),
+    Rotated(usize),
+    // 轮盘已满, 等待替换
+    Full(u32),
+    Swapped { added: u32, removed: Option<u32> },
+    Reset,
+}
+
+// 轮盘变化只在 debug 级别输出, 用 ascend::roulette=debug 打开
+fn log_roulette_changes(mut events: EventReader<RouletteChangedEvent>) {
+    for event in events.read() {
+        debug!("roulette changed: {:?}", event);
+    }
+}
+
 // 添加物品
 pub fn on_add_item(
-    mut cmds: Commands,
     mut roulette_item_add_events: EventReader<RouletteItemAddEvent>,
     mut query_roulette: Query<&mut Roulette>,
-    query_player_e: Query<Entity, With<Player>>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    for event in roulette_item_add_events.read() {
+        let charges = find_item_info(&item_infos, event.id)
+            .map(|info| info.charges)
+            .unwrap_or_default();
+        let changed = match roulette.add_item(event.id, charges) {
+            RouletteAdd::Added(_) => RouletteChangedEvent::Added(event.id),
+            RouletteAdd::Stacked(_) => RouletteChangedEvent::Stacked(event.id),
+            RouletteAdd::Full => RouletteChangedEvent::Full(event.id),
+            RouletteAdd::Rejected => {
+                debug!("roulette full, item {} rejected", event.id);
+                continue;
+            }
+        };
+        roulette.wake();
+        changed_event.send(changed);
+    }
+}
+
+pub fn on_remove_item(
+    mut events: EventReader<RouletteItemRemoveEvent>,
+    mut query_roulette: Query<&mut Roulette>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    for event in events.read() {
+        if roulette.remove_id(event.id).is_some() {
+            roulette.wake();
+            changed_event.send(RouletteChangedEvent::Removed(event.id));
+        }
+    }
+}
+
+pub fn on_consume_item(
+    mut events: EventReader<RouletteConsumeEvent>,
+    mut query_roulette: Query<&mut Roulette>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    for _ in events.read() {
+        if let Some(id) = roulette.consume() {
+            changed_event.send(RouletteChangedEvent::Consumed(id));
+        }
+    }
+}
+
+// 开始转盘抽取
+#[derive(Event)]
+pub struct RouletteSpinEvent;
+
+// 转盘停下, 子弹和技能使用抽中的物品
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RouletteSpinSettledEvent {
+    pub id: u32,
+    pub index: usize,
+}
+
+pub fn on_spin(
+    mut events: EventReader<RouletteSpinEvent>,
+    mut query_roulette: Query<&mut Roulette>,
+    mut rng: ResMut<RouletteRng>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    for _ in events.read() {
+        if !roulette.can_spin() {
+            continue;
+        }
+        let weights: Vec<f32> = roulette
+            .get_all_items()
+            .iter()
+            .map(|slot| find_item_info(&item_infos, slot.id).map(|info| info.weight).unwrap_or(1.))
+            .collect();
+        let Some(target) = pick_weighted(&weights, rng.next_f32()) else {
+            continue;
+        };
+        roulette.start_spin(target, SPIN_LAPS);
+        roulette.wake();
+    }
+}
+
+pub fn tick_spin(
+    time: Res<Time>,
+    mut query_roulette: Query<&mut Roulette>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
+    mut settled_event: EventWriter<RouletteSpinSettledEvent>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    match roulette.tick_spin(time.delta()) {
+        Some(SpinTick::Stepped(index)) => {
+            roulette.wake();
+            changed_event.send(RouletteChangedEvent::Rotated(index));
+        }
+        Some(SpinTick::Settled(index)) => {
+            roulette.wake();
+            changed_event.send(RouletteChangedEvent::Rotated(index));
+            if let Some(slot) = roulette.get_item(index) {
+                debug!("spin settled on item {} at {}", slot.id, index);
+                settled_event.send(RouletteSpinSettledEvent {
+                    id: slot.id,
+                    index,
+                });
+            }
+        }
+        None => {}
+    }
+}
+
+// 轮盘满时: SwapItem 用新物品替换当前物品, DiscardItem 丢弃新物品
+pub fn choose_swap(
+    input: ActionInput,
+    mut query_roulette: Query<&mut Roulette>,
+    mut changed_event: EventWriter<RouletteChangedEvent>,
+) {
+    let mut roulette = query_roulette.single_mut();
+    let Some(pending) = roulette.get_pending().copied() else {
+        return;
+    };
+    if input.just_pressed(Action::SwapItem) {
+        let removed = roulette.swap_pending().map(|slot| slot.id);
+        roulette.wake();
+        changed_event.send(RouletteChangedEvent::Swapped { added: pending.id, removed });
+    } else if input.just_pressed(Action::DiscardItem) {
+        roulette.discard_pending();
+    }
+}
+
+// 轮盘内容变化时重新生成 UI 节点
+fn rebuild_wheel(
+    mut cmds: Commands,
+    mut events: EventReader<RouletteChangedEvent>,
+    query_roulette: Query<&Roulette>,
+    query_wheel: Query<Entity, With<RouletteWheel>>,
+    query_roulette_item: Query<Entity, With<RouletteItem>>,
     asset_server: Res<AssetServer>,
-    global_data: Res<GlobalData>,
-    mut show_event: EventWriter<ShowItemEvent>,
     item_infos: Res<Assets<RouletteItemInfo>>,
 ) {
-    if let Ok(player) = query_player_e.get_single()
-    {
-        let mut roulette = query_roulette.single_mut();
-        for event in roulette_item_add_events.read() {
-            cmds.entity(player).with_children(|parent| {
-                let roulette_len = roulette.len();
-                let cur_index = roulette.get_cur_index();
-                let transform_x = (roulette_len - cur_index) as f32 * 200. * global_data.scale;
-                // let item_info = item_infos.get(&event.id).unwrap();
-                let id = parent.spawn((
-                    RouletteItem {
-                        id: event.id,
-                    }, SpriteBundle {
-                        sprite: Sprite {
-                            ..default()
-                        },
-                        texture: asset_server.load("images/player.png"),
-                        transform: Transform {
-                            translation: Vec3::new(transform_x, 200., 0.),
-                            scale: Vec3::splat(5.0),
-                            ..default()
-                        },
+    let rebuild = events
+        .read()
+        .any(|event| !matches!(event, RouletteChangedEvent::Rotated(_) | RouletteChangedEvent::Full(_)));
+    if !rebuild {
+        return;
+    }
+    let Ok(wheel) = query_wheel.get_single() else {
+        return;
+    };
+    for entity in query_roulette_item.iter() {
+        cmds.entity(entity).despawn_recursive();
+    }
+
+    let roulette = query_roulette.single();
+    cmds.entity(wheel).with_children(|parent| {
+        for (index, slot) in roulette.get_all_items().iter().enumerate() {
+            let texture = find_item_info(&item_infos, slot.id)
+                .map(|info| info.texture.clone())
+                .unwrap_or("images/player.png".to_string());
+            // 有次数限制时显示剩余次数, 否则显示叠加数量
+            let label = if slot.max_charges > 0 {
+                format!("{}/{}", slot.charges, slot.max_charges)
+            } else if slot.count > 1 {
+                format!("x{}", slot.count)
+            } else {
+                String::new()
+            };
+            parent.spawn((
+                RouletteItem {
+                    id: slot.id,
+                    index,
+                }, ImageBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(ITEM_SIZE),
+                        height: Val::Px(ITEM_SIZE),
+                        ..default()
+                    },
+                    image: UiImage::new(asset_server.load(texture)),
+                    ..default()
+                },
+            )).with_children(|parent| {
+                parent.spawn(TextBundle {
+                    text: Text::from_section(label, TextStyle {
+                        font_size: 14.,
+                        color: Color::BLACK,
+                        ..default()
+                    }),
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        right: Val::Px(0.),
+                        bottom: Val::Px(0.),
                         ..default()
                     },
-                )).id();
-                roulette.add_item(id);
-                roulette.timer.reset();
-                show_event.send(ShowItemEvent::All);
+                    ..default()
+                });
             });
         }
+    });
+}
+
+// 轮盘中的道具图标和玩家朝向一致
+fn face_wheel_items(
+    query_player: Query<Ref<Facing>, With<Player>>,
+    mut query_item: Query<(Ref<RouletteItem>, &mut UiImage)>,
+) {
+    let Ok(facing) = query_player.get_single() else {
+        return;
+    };
+    for (item, mut image) in query_item.iter_mut() {
+        if facing.is_changed() || item.is_added() {
+            image.flip_x = facing.is_left();
+        }
     }
 }
 
+#[cfg(feature = "dev")]
 pub fn test_add_item(
     mut events: EventWriter<RouletteItemAddEvent>,
     input: Res<ButtonInput<KeyCode>>,
@@ -240,66 +871,307 @@ pub fn test_add_item(
     }
 }
 
-pub fn timer_hide_item(
+#[cfg(feature = "dev")]
+fn console_give(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let id: u32 = console_arg(args, 0, "item_id")?;
+    let count: u32 = if args.len() > 1 { console_arg(args, 1, "count")? } else { 1 };
+    let name = find_item_info(world.resource::<Assets<RouletteItemInfo>>(), id)
+        .map(|info| info.name.clone())
+        .ok_or_else(|| format!("no item with id {}", id))?;
+    for _ in 0..count {
+        world.send_event(RouletteItemAddEvent { id });
+    }
+    Ok(format!("gave {} x{}", name, count))
+}
+
+#[cfg(feature = "dev")]
+fn console_take(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let id: u32 = console_arg(args, 0, "item_id")?;
+    let mut query = world.query::<&Roulette>();
+    let held = query.iter(world).any(|roulette| roulette.find(id).is_some());
+    if !held {
+        return Err(format!("item {} is not in the roulette", id));
+    }
+    world.send_event(RouletteItemRemoveEvent { id });
+    Ok(format!("took item {}", id))
+}
+
+// 把角度差限制在 [-PI, PI], 转动时走最短的方向
+fn shortest_angle(delta: f32) -> f32 {
+    (delta + PI).rem_euclid(TAU) - PI
+}
+
+// 轮盘旋转到当前物品, 当前物品在正上方并放大
+pub fn rotate_wheel(
+    time: Res<Time>,
+    query_roulette: Query<&Roulette>,
+    mut query_wheel: Query<&mut RouletteWheel>,
+    mut query_roulette_item: Query<(&RouletteItem, &mut Style)>,
+) {
+    let roulette = query_roulette.single();
+    let Ok(mut wheel) = query_wheel.get_single_mut() else {
+        return;
+    };
+    if roulette.len() == 0 {
+        return;
+    }
+
+    let step = TAU / roulette.len() as f32;
+    let target = roulette.get_cur_index() as f32 * step;
+    let t = 1. - (-WHEEL_ROTATE_SPEED * time.delta_seconds()).exp();
+    let angle = wheel.angle + shortest_angle(target - wheel.angle) * t;
+    wheel.angle = angle.rem_euclid(TAU);
+
+    for (item, mut style) in query_roulette_item.iter_mut() {
+        let i = item.index;
+        let theta = PI / 2. + i as f32 * step - wheel.angle;
+        let size = if i == roulette.get_cur_index() {
+            ITEM_SIZE * ITEM_HIGHLIGHT_SCALE
+        } else {
+            ITEM_SIZE
+        };
+        style.width = Val::Px(size);
+        style.height = Val::Px(size);
+        style.left = Val::Px(WHEEL_SIZE / 2. + WHEEL_RADIUS * theta.cos() - size / 2.);
+        style.top = Val::Px(WHEEL_SIZE / 2. - WHEEL_RADIUS * theta.sin() - size / 2.);
+    }
+}
+
+// Roulette::timer 结束后轮盘渐隐
+fn fade_wheel(
     time: Res<Time>,
     mut query_roulette: Query<&mut Roulette>,
-    mut query_roulette_item: Query<(&mut Visibility, &RouletteItem)>,
+    mut query_wheel: Query<&mut RouletteWheel>,
+    mut query_roulette_item: Query<(&RouletteItem, &mut BackgroundColor)>,
+    mut query_name: Query<&mut Text, With<RouletteItemName>>,
 ) {
     let mut roulette = query_roulette.single_mut();
+    let Ok(mut wheel) = query_wheel.get_single_mut() else {
+        return;
+    };
     roulette.timer.tick(time.delta());
-    
-    if roulette.timer.finished() {
-        if let Some(cur_item_id) = roulette.get_cur_item() {
-            for item_id in roulette.get_all_items() {
-                if let Ok((mut visibility, _)) = query_roulette_item.get_mut(*item_id) {
-                    if item_id != cur_item_id {
-                        // println!("hide item {:?}", item_id);
-                        *visibility = Visibility::Hidden;
-                    }
-                }
-            }
+
+    wheel.alpha = if roulette.timer.finished() {
+        (wheel.alpha - time.delta_seconds() / WHEEL_FADE_TIME).max(0.)
+    } else {
+        1.
+    };
+
+    for (item, mut color) in query_roulette_item.iter_mut() {
+        // 非当前物品稍暗一些
+        let tint = if item.index == roulette.get_cur_index() { 1. } else { 0.6 };
+        color.0 = Color::rgba(tint, tint, tint, wheel.alpha);
+    }
+    for mut text in query_name.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(wheel.alpha);
         }
     }
 }
 
-
-#[derive(Event)]
-pub enum ShowItemEvent {
-    All,
-    Three,
+// 在轮盘中间显示当前物品的名字
+fn update_item_name(
+    query_roulette: Query<Ref<Roulette>>,
+    mut query_name: Query<&mut Text, With<RouletteItemName>>,
+    item_infos: Res<Assets<RouletteItemInfo>>,
+    locale: Res<Locale>,
+) {
+    let Ok(roulette) = query_roulette.get_single() else {
+        return;
+    };
+    if !roulette.is_changed() && !locale.is_changed() {
+        return;
+    }
+    let name = roulette
+        .get_cur_item()
+        .and_then(|slot| find_item_info(&item_infos, slot.id))
+        .map(|info| locale.item_name(info))
+        .unwrap_or_default();
+    for mut text in query_name.iter_mut() {
+        text.sections[0].value = name.clone();
+    }
 }
 
-pub fn show_all_item(
-    mut events: EventReader<ShowItemEvent>,
-    roulette: Query<&Roulette>,
-    mut query_roulette_item: Query<(&mut Visibility, &RouletteItem)>,
-) {
-    for evt in events.read() {
-        let roulette = roulette.single();
-        match evt {
-            ShowItemEvent::All => {
-                for item_id in roulette.get_all_items() {
-                    if let Ok((mut visibility, _)) = query_roulette_item.get_mut(*item_id) {
-                        *visibility = Visibility::Visible;
-                    }
-                }
-            }
-            ShowItemEvent::Three => {
-                let cur_index = roulette.get_cur_index();
-                let pre_index = cur_index as i32 - 1;
-                let next_index = cur_index + 1;
-                println!("===== {} {} {}", pre_index, cur_index, next_index);
-                for i in 0..roulette.len() {
-                    let item_id = roulette.get_item(i).unwrap();
-                    if let Ok((mut visibility, _)) = query_roulette_item.get_mut(*item_id) {
-                        if (pre_index >= 0 && i as i32 == pre_index) || i == cur_index || (next_index < roulette.len() && i == next_index) {
-                            *visibility = Visibility::Visible;
-                        } else {
-                            *visibility = Visibility::Hidden;
-                        }
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_roulette_has_no_neighbours() {
+        let roulette = Roulette::new();
+        assert_eq!(roulette.get_prev_item(), None);
+        assert_eq!(roulette.get_next_item(), None);
+        assert_eq!(roulette.get_cur_item(), None);
+    }
+
+    #[test]
+    fn add_stacks_identical_ids() {
+        let mut roulette = Roulette::new();
+        assert_eq!(roulette.add_item(1, 0), RouletteAdd::Added(0));
+        assert_eq!(roulette.add_item(2, 0), RouletteAdd::Added(1));
+        assert_eq!(roulette.add_item(1, 0), RouletteAdd::Stacked(0));
+        assert_eq!(roulette.len(), 2);
+        assert_eq!(roulette.get_item(0).unwrap().count, 2);
+    }
+
+    #[test]
+    fn full_roulette_keeps_pending_item_for_swap() {
+        let mut roulette = Roulette::with_capacity(2);
+        roulette.add_item(1, 0);
+        roulette.add_item(2, 0);
+        assert_eq!(roulette.add_item(3, 0), RouletteAdd::Full);
+        assert_eq!(roulette.get_pending().map(|slot| slot.id), Some(3));
+
+        roulette.next();
+        let removed = roulette.swap_pending().unwrap();
+        assert_eq!(removed.id, 2);
+        assert_eq!(roulette.get_cur_item().unwrap().id, 3);
+        assert!(roulette.get_pending().is_none());
+
+        roulette.add_item(4, 0);
+        assert_eq!(roulette.discard_pending().map(|slot| slot.id), Some(4));
+        assert_eq!(roulette.len(), 2);
+    }
+
+    #[test]
+    fn consume_with_no_charges_left_does_not_underflow() {
+        let mut roulette = Roulette::new();
+        roulette.load(vec![RouletteSlot { charges: 0, ..RouletteSlot::new(1, 3) }], 0, None);
+        assert_eq!(roulette.get_cur_item().map(|slot| slot.charges), Some(1));
+        assert_eq!(roulette.consume(), Some(1));
+        assert!(roulette.is_empty());
+
+        let mut roulette = Roulette::new();
+        roulette.list.push(RouletteSlot { charges: 0, count: 2, ..RouletteSlot::new(2, 3) });
+        assert_eq!(roulette.consume(), Some(2));
+        let slot = roulette.get_cur_item().unwrap();
+        assert_eq!((slot.count, slot.charges), (1, 3));
+    }
+
+    #[test]
+    fn full_roulette_rejects_items_while_pending() {
+        let mut roulette = Roulette::with_capacity(2);
+        roulette.add_item(1, 0);
+        roulette.add_item(2, 0);
+        assert_eq!(roulette.add_item(3, 0), RouletteAdd::Full);
+        assert!(!roulette.can_accept(4));
+        assert_eq!(roulette.add_item(4, 0), RouletteAdd::Rejected);
+        assert_eq!(roulette.get_pending().map(|slot| slot.id), Some(3));
+
+        // 已有的物品仍然可以叠加
+        assert!(roulette.can_accept(1));
+        assert_eq!(roulette.add_item(1, 0), RouletteAdd::Stacked(0));
+
+        roulette.discard_pending();
+        assert!(roulette.can_accept(4));
+        assert_eq!(roulette.add_item(4, 0), RouletteAdd::Full);
+    }
+
+    #[test]
+    fn remove_keeps_cur_index_on_same_item() {
+        let mut roulette = Roulette::new();
+        roulette.add_item(1, 0);
+        roulette.add_item(2, 0);
+        roulette.add_item(3, 0);
+        roulette.next();
+        roulette.next();
+
+        assert_eq!(roulette.remove_id(1).map(|slot| slot.id), Some(1));
+        assert_eq!(roulette.get_cur_item().unwrap().id, 3);
+
+        roulette.remove_item(1);
+        assert_eq!(roulette.get_cur_index(), 0);
+        assert_eq!(roulette.get_cur_item().unwrap().id, 2);
+
+        roulette.remove_item(0);
+        assert!(roulette.is_empty());
+        assert_eq!(roulette.get_cur_index(), 0);
+        assert_eq!(roulette.remove_item(0), None);
+    }
+
+    #[test]
+    fn consume_uses_charges_then_stack() {
+        let mut roulette = Roulette::new();
+        roulette.add_item(1, 2);
+        roulette.add_item(1, 2);
+
+        assert_eq!(roulette.consume(), Some(1));
+        assert_eq!(roulette.get_cur_item().unwrap().charges, 1);
+        assert_eq!(roulette.consume(), Some(1));
+        let slot = roulette.get_cur_item().unwrap();
+        assert_eq!((slot.count, slot.charges), (1, 2));
+
+        roulette.consume();
+        roulette.consume();
+        assert!(roulette.is_empty());
+        assert_eq!(roulette.consume(), None);
+    }
+
+    #[test]
+    fn pick_weighted_respects_weights() {
+        let weights = [1., 0., 3.];
+        assert_eq!(pick_weighted(&weights, 0.), Some(0));
+        assert_eq!(pick_weighted(&weights, 0.24), Some(0));
+        assert_eq!(pick_weighted(&weights, 0.25), Some(2));
+        assert_eq!(pick_weighted(&weights, 0.999), Some(2));
+        assert_eq!(pick_weighted(&[0., 0.], 0.5), None);
+        assert_eq!(pick_weighted(&[], 0.5), None);
+    }
+
+    #[test]
+    fn rng_is_reproducible_from_seed() {
+        let mut a = RouletteRng::new(42);
+        let mut b = RouletteRng::new(42);
+        for _ in 0..100 {
+            let x = a.next_f32();
+            assert!((0. ..1.).contains(&x));
+            assert_eq!(x, b.next_f32());
+        }
+    }
+
+    #[test]
+    fn seed_parses_decimal_and_hex() {
+        assert_eq!(parse_seed(Some("42")), Some(42));
+        assert_eq!(parse_seed(Some(" 0xff ")), Some(255));
+        assert_eq!(parse_seed(Some("abc")), None);
+        assert_eq!(parse_seed(None), None);
+    }
+
+    #[test]
+    fn spin_lands_on_target_then_cools_down() {
+        let mut roulette = Roulette::new();
+        roulette.add_item(1, 0);
+        roulette.add_item(2, 0);
+        roulette.add_item(3, 0);
+        assert!(roulette.start_spin(2, 1));
+        assert!(!roulette.start_spin(1, 1));
+
+        let step = Duration::from_secs(1);
+        let mut steps = 0;
+        let settled = loop {
+            steps += 1;
+            match roulette.tick_spin(step) {
+                Some(SpinTick::Settled(index)) => break index,
+                Some(SpinTick::Stepped(_)) => {}
+                None => panic!("spin should advance every second"),
             }
+        };
+        assert_eq!(settled, 2);
+        assert_eq!(steps, 5);
+        assert_eq!(roulette.get_cur_item().unwrap().id, 3);
+        assert!(!roulette.can_spin());
+
+        roulette.tick_spin(Duration::from_secs_f32(SPIN_COOLDOWN));
+        assert!(roulette.can_spin());
+    }
+
+    #[test]
+    fn unlimited_items_are_never_used_up() {
+        let mut roulette = Roulette::new();
+        roulette.add_item(1, 0);
+        for _ in 0..10 {
+            assert_eq!(roulette.consume(), Some(1));
         }
+        assert_eq!(roulette.get_cur_item().unwrap().count, 1);
     }
-}
\ No newline at end of file
+}
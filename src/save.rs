@@ -0,0 +1,490 @@
+// 存档
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::roulette::RouletteItemAddEvent;
+
+// 存档格式版本, 修改 SaveData 结构时递增并在 migrate 中补充转换
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_DIR: &str = "saves";
+pub const SAVE_SLOT_COUNT: u8 = 3;
+
+pub fn save_plugin(app: &mut App) {
+    app
+        .init_resource::<SaveDir>()
+        .init_resource::<SaveSlot>()
+        .init_resource::<Progress>()
+        .init_resource::<ProgressLoaded>()
+        .add_event::<SaveEvent>()
+        .add_event::<LoadEvent>()
+        .add_systems(Startup, load_active_slot)
+        .add_systems(Update, (
+            on_load,
+            record_unlocked_level,
+            record_collected_item,
+            on_save,
+        ).chain());
+}
+
+// 存档目录, 测试中替换为临时目录
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct SaveDir(pub PathBuf);
+
+impl Default for SaveDir {
+    fn default() -> Self {
+        Self(PathBuf::from(SAVE_DIR))
+    }
+}
+
+// 当前使用的存档槽位
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveSlot(pub u8);
+
+// 存档读取成功之前不自动存档, 否则默认进度会覆盖已有存档
+#[derive(Resource, Default)]
+struct ProgressLoaded(bool);
+
+// 进度: 已解锁关卡, 收集过的道具, 各关卡最快通关时间
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Progress {
+    pub unlocked_levels: Vec<String>,
+    pub collected_items: Vec<u32>,
+    pub best_times: BTreeMap<String, f32>,
+}
+
+impl Progress {
+    pub fn unlock_level(&mut self, iid: &str) -> bool {
+        if self.unlocked_levels.iter().any(|l| l == iid) {
+            return false;
+        }
+        self.unlocked_levels.push(iid.to_string());
+        true
+    }
+
+    // 返回是否是第一次收集
+    pub fn collect_item(&mut self, id: u32) -> bool {
+        if self.collected_items.contains(&id) {
+            return false;
+        }
+        self.collected_items.push(id);
+        true
+    }
+
+    // 只保留更短的时间, 返回是否刷新了记录
+    pub fn record_time(&mut self, iid: &str, seconds: f32) -> bool {
+        match self.best_times.get(iid) {
+            Some(best) if *best <= seconds => false,
+            _ => {
+                self.best_times.insert(iid.to_string(), seconds);
+                true
+            }
+        }
+    }
+}
+
+// 设置保存在 settings.ron 中, 旧存档里的 settings 字段读取时忽略
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SaveData {
+    pub progress: Progress,
+}
+
+// 写入磁盘的外层结构, checksum 用于检测存档损坏
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    checksum: u64,
+    data: String,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    Corrupted,
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "io error: {}", e),
+            SaveError::Serialize(e) => write!(f, "serialize error: {}", e),
+            SaveError::Deserialize(e) => write!(f, "deserialize error: {}", e),
+            SaveError::Corrupted => write!(f, "checksum mismatch"),
+            SaveError::UnsupportedVersion(v) => write!(f, "unsupported save version {}", v),
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+pub fn slot_path(dir: &Path, slot: u8) -> PathBuf {
+    dir.join(format!("slot_{}.ron", slot))
+}
+
+fn backup_path(dir: &Path, slot: u8) -> PathBuf {
+    slot_path(dir, slot).with_extension("ron.bak")
+}
+
+// FNV-1a
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn encode(data: &SaveData) -> Result<String, SaveError> {
+    let data = ron::to_string(data).map_err(SaveError::Serialize)?;
+    let file = SaveFile {
+        version: SAVE_VERSION,
+        checksum: checksum(data.as_bytes()),
+        data,
+    };
+    ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default()).map_err(SaveError::Serialize)
+}
+
+pub fn decode(text: &str) -> Result<SaveData, SaveError> {
+    let file: SaveFile = ron::from_str(text).map_err(|_| SaveError::Corrupted)?;
+    if checksum(file.data.as_bytes()) != file.checksum {
+        return Err(SaveError::Corrupted);
+    }
+    migrate(file.version, &file.data)
+}
+
+// 按版本把旧存档转换成当前结构
+// 新增字段靠 #[serde(default)] 兼容, 字段改名或语义变化时在这里增加分支
+fn migrate(version: u32, data: &str) -> Result<SaveData, SaveError> {
+    match version {
+        SAVE_VERSION => ron::from_str(data).map_err(SaveError::Deserialize),
+        v => Err(SaveError::UnsupportedVersion(v)),
+    }
+}
+
+// 先写临时文件再 rename, 保证中途崩溃不会留下半个存档
+pub fn write_slot(dir: &Path, slot: u8, data: &SaveData) -> Result<(), SaveError> {
+    let _span = info_span!("write_slot", slot).entered();
+    let text = encode(data)?;
+    let path = slot_path(dir, slot);
+    fs::create_dir_all(dir)?;
+
+    let tmp_path = path.with_extension("ron.tmp");
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+    }
+    if path.exists() {
+        fs::copy(&path, backup_path(dir, slot))?;
+    }
+    fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
+
+pub fn read_slot(dir: &Path, slot: u8) -> Result<Option<SaveData>, SaveError> {
+    read_with_backup(&slot_path(dir, slot), &backup_path(dir, slot))
+}
+
+fn read_file(path: &Path) -> Result<SaveData, SaveError> {
+    decode(&fs::read_to_string(path)?)
+}
+
+// 主存档读取失败或损坏时回退到上一次的备份
+fn read_with_backup(path: &Path, backup: &Path) -> Result<Option<SaveData>, SaveError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match read_file(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) => {
+            warn!("save {} is unreadable ({}), trying backup", path.display(), e);
+            if !backup.exists() {
+                return Err(e);
+            }
+            read_file(backup).map(Some)
+        }
+    }
+}
+
+#[derive(Event)]
+pub struct SaveEvent;
+
+#[derive(Event)]
+pub struct LoadEvent {
+    pub slot: u8,
+}
+
+// 启动时读取当前槽位
+fn load_active_slot(slot: Res<SaveSlot>, mut load_event: EventWriter<LoadEvent>) {
+    load_event.send(LoadEvent { slot: slot.0 });
+}
+
+// 读取失败时保持未读取状态, 不自动存档, 以免覆盖损坏但可能修复的存档
+fn on_load(
+    mut events: EventReader<LoadEvent>,
+    dir: Res<SaveDir>,
+    mut slot: ResMut<SaveSlot>,
+    mut progress: ResMut<Progress>,
+    mut loaded: ResMut<ProgressLoaded>,
+) {
+    for event in events.read() {
+        if event.slot >= SAVE_SLOT_COUNT {
+            warn!("save slot {} out of range", event.slot);
+            continue;
+        }
+        slot.0 = event.slot;
+        match read_slot(&dir.0, event.slot) {
+            Ok(Some(data)) => {
+                *progress = data.progress;
+                loaded.0 = true;
+            }
+            Ok(None) => {
+                *progress = Progress::default();
+                loaded.0 = true;
+            }
+            Err(e) => {
+                error!("failed to load save slot {}, auto-save disabled: {}", event.slot, e);
+                *progress = Progress::default();
+                loaded.0 = false;
+            }
+        }
+    }
+}
+
+fn on_save(
+    mut events: EventReader<SaveEvent>,
+    dir: Res<SaveDir>,
+    slot: Res<SaveSlot>,
+    progress: Res<Progress>,
+    loaded: Res<ProgressLoaded>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    if !loaded.0 {
+        warn!("save slot {} not loaded, skip saving", slot.0);
+        return;
+    }
+    let data = SaveData {
+        progress: progress.clone(),
+    };
+    if let Err(e) = write_slot(&dir.0, slot.0, &data) {
+        error!("failed to write save slot {}: {}", slot.0, e);
+    }
+}
+
+// 本次游戏中进入过的关卡和第一次进入的时间
+#[derive(Default)]
+struct VisitedLevels {
+    current: Option<String>,
+    entered: BTreeMap<String, f32>,
+}
+
+// 进入新关卡时解锁, 有变化就自动存档
+// 第一次到达更高的关卡才算通过上一关, 在相邻关卡之间来回走动不记录时间
+fn record_unlocked_level(
+    level_selection: Res<LevelSelection>,
+    time: Res<Time>,
+    query_level: Query<(&LevelIid, &GlobalTransform)>,
+    mut progress: ResMut<Progress>,
+    mut save_event: EventWriter<SaveEvent>,
+    mut visited: Local<VisitedLevels>,
+) {
+    if !level_selection.is_changed() {
+        return;
+    }
+    let LevelSelection::Iid(level_iid) = level_selection.as_ref() else {
+        return;
+    };
+    let iid = level_iid.to_string();
+    let now = time.elapsed_seconds();
+
+    if visited.current.as_ref() == Some(&iid) {
+        return;
+    }
+    let previous = visited.current.replace(iid.clone());
+    let first_visit = !visited.entered.contains_key(&iid);
+    if first_visit {
+        visited.entered.insert(iid.clone(), now);
+    }
+
+    let mut changed = progress.unlock_level(&iid);
+    if let Some(previous) = previous.filter(|_| first_visit) {
+        let level_y = |target: &str| {
+            query_level
+                .iter()
+                .find(|(level, _)| level.to_string() == target)
+                .map(|(_, transform)| transform.translation().y)
+        };
+        let ascended = match (level_y(&previous), level_y(&iid)) {
+            (Some(from), Some(to)) => to > from,
+            _ => false,
+        };
+        if let (true, Some(start)) = (ascended, visited.entered.get(&previous)) {
+            changed |= progress.record_time(&previous, now - start);
+        }
+    }
+
+    if changed {
+        save_event.send(SaveEvent);
+    }
+}
+
+// 第一次收集到道具时自动存档
+fn record_collected_item(
+    mut events: EventReader<RouletteItemAddEvent>,
+    mut progress: ResMut<Progress>,
+    mut save_event: EventWriter<SaveEvent>,
+) {
+    let mut changed = false;
+    for event in events.read() {
+        changed |= progress.collect_item(event.id);
+    }
+    if changed {
+        save_event.send(SaveEvent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SaveData {
+        let mut progress = Progress::default();
+        progress.unlock_level("level_0");
+        progress.collect_item(3);
+        progress.record_time("level_0", 12.5);
+        SaveData { progress }
+    }
+
+    // 每个测试用自己的目录, 避免并行测试互相覆盖
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ascend_save_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let data = sample();
+        let text = encode(&data).unwrap();
+        assert_eq!(decode(&text).unwrap(), data);
+    }
+
+    #[test]
+    fn tampered_data_is_corrupted() {
+        let text = encode(&sample()).unwrap();
+        let tampered = text.replace("level_0", "level_9");
+        assert!(matches!(decode(&tampered), Err(SaveError::Corrupted)));
+        assert!(matches!(decode("not a save"), Err(SaveError::Corrupted)));
+    }
+
+    #[test]
+    fn migrate_rejects_unknown_version() {
+        let data = ron::to_string(&sample()).unwrap();
+        assert_eq!(migrate(SAVE_VERSION, &data).unwrap(), sample());
+        assert!(matches!(migrate(SAVE_VERSION + 1, &data), Err(SaveError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn migrate_fills_missing_fields_with_defaults() {
+        let data = migrate(SAVE_VERSION, "(progress: (unlocked_levels: [\"a\"]))").unwrap();
+        assert_eq!(data.progress.unlocked_levels, vec!["a".to_string()]);
+        assert!(data.progress.collected_items.is_empty());
+    }
+
+    #[test]
+    fn collect_item_reports_first_time_only() {
+        let mut progress = Progress::default();
+        assert!(progress.collect_item(1));
+        assert!(!progress.collect_item(1));
+    }
+
+    #[test]
+    fn corrupted_save_falls_back_to_backup() {
+        let dir = temp_dir("corrupted");
+        let (path, backup) = (dir.join("slot.ron"), dir.join("slot.ron.bak"));
+        fs::write(&path, "broken").unwrap();
+        fs::write(&backup, encode(&sample()).unwrap()).unwrap();
+        assert_eq!(read_with_backup(&path, &backup).unwrap(), Some(sample()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_save_falls_back_to_backup() {
+        let dir = temp_dir("unreadable");
+        // 目录存在但不能当作文件读取
+        let (path, backup) = (dir.join("slot.ron"), dir.join("slot.ron.bak"));
+        fs::create_dir_all(&path).unwrap();
+        fs::write(&backup, encode(&sample()).unwrap()).unwrap();
+        assert_eq!(read_with_backup(&path, &backup).unwrap(), Some(sample()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn startup_loads_save_before_auto_save() {
+        let dir = temp_dir("startup");
+        write_slot(&dir, 0, &sample()).unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(SaveDir(dir.clone()))
+            .init_resource::<Time>()
+            .add_event::<RouletteItemAddEvent>()
+            .insert_resource(LevelSelection::iid("level_1"))
+            .add_plugins(save_plugin);
+        app.world.send_event(RouletteItemAddEvent { id: 4 });
+        // 进入新关卡和收集道具都会触发自动存档
+        app.update();
+        app.update();
+
+        let progress = read_slot(&dir, 0).unwrap().unwrap().progress;
+        assert_eq!(progress.unlocked_levels, vec!["level_0".to_string(), "level_1".to_string()]);
+        assert_eq!(progress.collected_items, vec![3, 4]);
+        assert_eq!(progress.best_times.get("level_0"), Some(&12.5));
+        let backup = read_file(&backup_path(&dir, 0)).unwrap().progress;
+        assert!(backup.unlocked_levels.contains(&"level_0".to_string()));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_slot_is_not_overwritten() {
+        let dir = temp_dir("no_overwrite");
+        fs::write(slot_path(&dir, 0), "broken").unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(SaveDir(dir.clone()))
+            .init_resource::<Time>()
+            .add_event::<RouletteItemAddEvent>()
+            .insert_resource(LevelSelection::iid("level_1"))
+            .add_plugins(save_plugin);
+        app.update();
+
+        assert_eq!(fs::read_to_string(slot_path(&dir, 0)).unwrap(), "broken");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn missing_save_is_none() {
+        let dir = temp_dir("missing");
+        assert_eq!(read_with_backup(&dir.join("slot.ron"), &dir.join("slot.ron.bak")).unwrap(), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
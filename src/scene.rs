@@ -5,10 +5,19 @@ use bevy::ui::DefaultCameraView;
 use bevy::utils::HashSet;
 use bevy_ecs_ldtk::{prelude::*, utils::ldtk_pixel_coords_to_translation_pivoted};
 use bevy_rapier2d::prelude::*;
-
-use crate::bullet::AtkNormal;
-use crate::enemy::Enemy;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::{
+    ground_sensor_groups, BulletHitColorItem, GroundLeft, GroundTouched, Layer, PlayerEnteredDoor, PlayerLeftDoor,
+};
+use crate::audio::{PlaySfxEvent, Sfx};
+use crate::comm::{Facing, Health};
+#[cfg(feature = "dev")]
+use crate::console::{console_arg, AddConsoleCommand, ConsoleResult};
+use crate::enemy::{Enemy, EnemyBrain, EnemyKind, EnemyStats};
 use crate::enemy::Patrol;
+use crate::locale::LocalizedText;
+use crate::pickup::Chest;
 use crate::player::Player;
 use crate::wall::Collidable;
 
@@ -18,45 +27,50 @@ pub fn scene_plugin(app: &mut App) {
     .add_systems(
         Update,
         (
-            camera_fit_inside_current_level,
             update_level_selection,
             on_spawn_ground_sensor,
             ground_detection,
             update_on_ground,
             check_door_color,
+            sync_door_background,
+            sync_door_lock,
             check_door_open,
         ),
     );
+
+    #[cfg(feature = "dev")]
+    {
+        app.add_console_command("level", "level <iid|identifier>: 把玩家移动到关卡中心", console_level);
+    }
 }
 
-#[derive(Component)]
-enum ColorItem {
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorItem {
     Yellow,
     Red,
 }
 
-#[derive(Component)]
-struct DoorColorHandle(Handle<Image>);
+impl ColorItem {
+    pub fn door_texture(&self) -> &'static str {
+        match self {
+            ColorItem::Yellow => "images/door_yellow.png",
+            ColorItem::Red => "images/door_red.png",
+        }
+    }
+}
 
-#[derive(Component)]
-struct Door;
+// 门的状态, color 为被子弹打中的颜色道具
+// locked 时门变为实心碰撞体, 用于 Boss 战封锁场地
+#[derive(Component, Default)]
+pub struct Door {
+    pub color: Option<ColorItem>,
+    pub locked: bool,
+}
 
 fn setup(
     mut cmds: Commands,
     asset_server: Res<AssetServer>,
 ) {
-    cmds.spawn((
-        SpriteBundle {
-            sprite: Sprite {
-                ..Default::default()
-            },
-            transform: Transform::from_xyz(0., 0., -1.0).with_scale(Vec3 { x: 0.4, y: 0.4, z: 0. }),
-            texture: asset_server.load("images/bg.png"),
-            ..Default::default()
-        },
-        Name::new("bg"),
-    ));
-
     cmds.spawn((
         SpriteBundle {
             sprite: Sprite {
@@ -69,7 +83,8 @@ fn setup(
         Name::new("door"),
         Collider::cuboid(110., 850.),
         Sensor,
-        Door,
+        Layer::Sensor.groups(),
+        Door::default(),
     ));
     
     cmds.spawn((
@@ -83,6 +98,7 @@ fn setup(
         },
         Name::new("floor"),
         Collider::cuboid(1000., 50.),
+        Layer::Wall.groups(),
     ));
 
     cmds.spawn((
@@ -95,10 +111,10 @@ fn setup(
             ..Default::default()
         },
         ColorItem::Yellow,
-        DoorColorHandle(asset_server.load("images/door_yellow.png")),
         Name::new("yellow_item"),
         Collider::ball(110.),
         Sensor,
+        Layer::Sensor.groups(),
     ));
 
     cmds.spawn((
@@ -111,10 +127,10 @@ fn setup(
             ..Default::default()
         },
         ColorItem::Red,
-        DoorColorHandle(asset_server.load("images/door_red.png")),
         Name::new("red_item"),
         Collider::ball(140.),
         Sensor,
+        Layer::Sensor.groups(),
     ));
 }
 
@@ -130,6 +146,9 @@ pub struct ChestBundle {
     pub sprite_sheet_bundle: SpriteSheetBundle,
     #[from_entity_instance]
     pub collider_bundle: ColliderBundle,
+    pub chest: Chest,
+    #[from_entity_instance]
+    pub items: Items,
 }
 
 #[derive(Clone, Default, Bundle, LdtkEntity)]
@@ -141,19 +160,70 @@ pub struct MobBundle {
     pub enemy: Enemy,
     #[ldtk_entity]
     pub patrol: Patrol,
+    #[from_entity_instance]
+    pub enemy_bundle: EnemyBundle,
+}
+
+// 飞行敌人和炮台没有巡逻路线
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct FlyerBundle {
+    #[sprite_sheet_bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    pub enemy: Enemy,
+    #[from_entity_instance]
+    pub enemy_bundle: EnemyBundle,
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct TurretBundle {
+    #[sprite_sheet_bundle]
+    pub sprite_sheet_bundle: SpriteSheetBundle,
+    #[from_entity_instance]
+    pub collider_bundle: ColliderBundle,
+    pub enemy: Enemy,
+    #[from_entity_instance]
+    pub enemy_bundle: EnemyBundle,
+}
+
+#[derive(Clone, Default, Bundle)]
+pub struct EnemyBundle {
+    pub kind: EnemyKind,
+    pub stats: EnemyStats,
+    pub brain: EnemyBrain,
+    pub health: Health,
+    pub facing: Facing,
+}
+
+impl From<&EntityInstance> for EnemyBundle {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        EnemyBundle {
+            kind: entity_instance.into(),
+            stats: entity_instance.into(),
+            brain: EnemyBrain::default(),
+            health: entity_instance.into(),
+            facing: Facing::default(),
+        }
+    }
 }
 
 #[derive(Clone, Component, Debug, Eq, Default, PartialEq)]
-pub struct Items(Vec<String>);
+pub struct Items(pub Vec<String>);
 
+// 不同实体的物品字段名不同: 宝箱为 content, 怪物为 loot, 其余为 items
 impl From<&EntityInstance> for Items {
     fn from(entity_instance: &EntityInstance) -> Self {
+        let field = match entity_instance.identifier.as_ref() {
+            "Chest" => "content",
+            "Mob" => "loot",
+            _ => "items",
+        };
         Items(
             entity_instance
-                .iter_enums_field("items")
-                .expect("items field should be correctly typed")
-                .cloned()
-                .collect(),
+                .iter_enums_field(field)
+                .map(|items| items.cloned().collect())
+                .unwrap_or_default(),
         )
     }
 }
@@ -167,6 +237,15 @@ pub struct ColliderBundle {
     pub gravity_scale: GravityScale,
     pub friction: Friction,
     pub density: ColliderMassProperties,
+    pub collision_groups: CollisionGroups,
+    pub solver_groups: SolverGroups,
+}
+
+impl ColliderBundle {
+    pub fn with_layer(mut self, layer: Layer) -> Self {
+        (self.collision_groups, self.solver_groups) = layer.groups();
+        self
+    }
 }
 
 impl From<&EntityInstance> for ColliderBundle {
@@ -183,13 +262,25 @@ impl From<&EntityInstance> for ColliderBundle {
                 },
                 rotation_constraints,
                 ..Default::default()
-            },
-            "Mob" => ColliderBundle {
+            }.with_layer(Layer::Player),
+            "Mob" | "Flyer" => ColliderBundle {
                 collider: Collider::cuboid(5., 5.),
                 rigid_body: RigidBody::KinematicVelocityBased,
                 rotation_constraints,
                 ..Default::default()
-            },
+            }.with_layer(Layer::Enemy),
+            "Boss" => ColliderBundle {
+                collider: Collider::cuboid(16., 16.),
+                rigid_body: RigidBody::KinematicVelocityBased,
+                rotation_constraints,
+                ..Default::default()
+            }.with_layer(Layer::Enemy),
+            "Turret" => ColliderBundle {
+                collider: Collider::cuboid(8., 8.),
+                rigid_body: RigidBody::Fixed,
+                rotation_constraints,
+                ..Default::default()
+            }.with_layer(Layer::Enemy),
             "Chest" => ColliderBundle {
                 collider: Collider::cuboid(8., 8.),
                 rigid_body: RigidBody::Dynamic,
@@ -198,7 +289,7 @@ impl From<&EntityInstance> for ColliderBundle {
                 friction: Friction::new(0.5),
                 density: ColliderMassProperties::Density(15.0),
                 ..Default::default()
-            },
+            }.with_layer(Layer::Wall),
             _ => ColliderBundle::default(),
         }
     }
@@ -212,6 +303,7 @@ pub struct SensorBundle {
     pub collider: Collider,
     pub sensor: Sensor,
     pub rotation_constraints: LockedAxes,
+    pub collision_groups: CollisionGroups,
 }
 
 impl From<IntGridCell> for SensorBundle {
@@ -224,6 +316,7 @@ impl From<IntGridCell> for SensorBundle {
                 collider: Collider::cuboid(8., 8.),
                 sensor: Sensor,
                 rotation_constraints,
+                collision_groups: Layer::Sensor.collision_groups(),
             }
         } else {
             SensorBundle::default()
@@ -271,6 +364,7 @@ pub fn on_spawn_ground_sensor(
                     .insert(ActiveEvents::COLLISION_EVENTS)
                     .insert(detector_shape)
                     .insert(Sensor)
+                    .insert(ground_sensor_groups())
                     .insert(Transform::from_translation(sensor_translation))
                     .insert(GlobalTransform::default());
             });
@@ -280,33 +374,17 @@ pub fn on_spawn_ground_sensor(
 
 pub fn ground_detection(
     mut ground_sensors: Query<&mut GroundSensor>,
-    mut collisions: EventReader<CollisionEvent>,
-    collidables: Query<Entity, With<Collider>>,
+    mut touched_events: EventReader<GroundTouched>,
+    mut left_events: EventReader<GroundLeft>,
 ) {
-    for collision_event in collisions.read() {
-        match collision_event {
-            CollisionEvent::Started(e1, e2, _) => {
-                if collidables.contains(*e1) {
-                    if let Ok(mut sensor) = ground_sensors.get_mut(*e2) {
-                        sensor.intersecting_ground_entities.insert(*e1);
-                    }
-                } else if collidables.contains(*e2) {
-                    if let Ok(mut sensor) = ground_sensors.get_mut(*e1) {
-                        sensor.intersecting_ground_entities.insert(*e2);
-                    }
-                }
-            }
-            CollisionEvent::Stopped(e1, e2, _) => {
-                if collidables.contains(*e1) {
-                    if let Ok(mut sensor) = ground_sensors.get_mut(*e2) {
-                        sensor.intersecting_ground_entities.remove(e1);
-                    }
-                } else if collidables.contains(*e2) {
-                    if let Ok(mut sensor) = ground_sensors.get_mut(*e1) {
-                        sensor.intersecting_ground_entities.remove(e2);
-                    }
-                }
-            }
+    for event in touched_events.read() {
+        if let Ok(mut sensor) = ground_sensors.get_mut(event.sensor) {
+            sensor.intersecting_ground_entities.insert(event.ground);
+        }
+    }
+    for event in left_events.read() {
+        if let Ok(mut sensor) = ground_sensors.get_mut(event.sensor) {
+            sensor.intersecting_ground_entities.remove(&event.ground);
         }
     }
 }
@@ -348,136 +426,86 @@ pub fn update_level_selection(
                 && player_transform.translation.y > level_bounds.min.y
                 && !level_selection.is_match(&LevelIndices::default(), level)
             {
+                info!("entered level {} ({})", level.identifier, level.iid);
                 *level_selection = LevelSelection::iid(level.iid.clone());
             }
         }
     }
 }
 
-const ASPECT_RATIO: f32 = 16. / 9.;
+#[derive(Component)]
+struct DoorBackgound;
 
-#[allow(clippy::type_complexity)]
-pub fn camera_fit_inside_current_level(
-    mut camera_query: Query<
-        (
-            &mut bevy::render::camera::OrthographicProjection,
-            &mut Transform,
-        ),
-        (With<IsDefaultUiCamera>, Without<Player>),
-    >,
-    player_query: Query<&Transform, With<Player>>,
-    level_query: Query<(&Transform, &LevelIid), (Without<OrthographicProjection>, Without<Player>)>,
-    ldtk_projects: Query<&Handle<LdtkProject>>,
-    level_selection: Res<LevelSelection>,
-    ldtk_project_assets: Res<Assets<LdtkProject>>,
-) {
-    if let Ok(Transform {
-        translation: player_translation,
-        ..
-    }) = player_query.get_single()
-    {
-        let player_translation = *player_translation;
-
-        let (mut orthographic_projection, mut camera_transform) = camera_query.single_mut();
-
-        for (level_transform, level_iid) in &level_query {
-            let ldtk_project = ldtk_project_assets
-                .get(ldtk_projects.single())
-                .expect("Project should be loaded if level has spawned");
-
-            let level = ldtk_project
-                .get_raw_level_by_iid(&level_iid.to_string())
-                .expect("Spawned level should exist in LDtk project");
-
-            if level_selection.is_match(&LevelIndices::default(), level) {
-                let level_ratio = level.px_wid as f32 / level.px_hei as f32;
-                orthographic_projection.viewport_origin = Vec2::ZERO;
-                if level_ratio > ASPECT_RATIO {
-                    // level is wider than the screen
-                    let height = (level.px_hei as f32 / 9.).round() * 9.;
-                    let width = height * ASPECT_RATIO;
-                    orthographic_projection.scaling_mode =
-                        bevy::render::camera::ScalingMode::Fixed { width, height };
-                    camera_transform.translation.x =
-                        (player_translation.x - level_transform.translation.x - width / 2.)
-                            .clamp(0., level.px_wid as f32 - width);
-                    camera_transform.translation.y = 0.;
-                } else {
-                    // level is taller than the screen
-                    let width = (level.px_wid as f32 / 16.).round() * 16.;
-                    let height = width / ASPECT_RATIO;
-                    orthographic_projection.scaling_mode =
-                        bevy::render::camera::ScalingMode::Fixed { width, height };
-                    camera_transform.translation.y =
-                        (player_translation.y - level_transform.translation.y - height / 2.)
-                            .clamp(0., level.px_hei as f32 - height);
-                    camera_transform.translation.x = 0.;
-                }
-
-                camera_transform.translation.x += level_transform.translation.x;
-                camera_transform.translation.y += level_transform.translation.y;
-            }
+// 有多扇门时只改变离颜色道具最近的门
+fn check_door_color(
+    mut events: EventReader<BulletHitColorItem>,
+    query_color_item: Query<(&ColorItem, &GlobalTransform)>,
+    mut query_door: Query<(&mut Door, &GlobalTransform)>,
+    mut sfx_event: EventWriter<PlaySfxEvent>,
+) 
+{
+    for event in events.read() {
+        let Ok((color_item, item_transform)) = query_color_item.get(event.item) else {
+            continue;
+        };
+        let item_pos = item_transform.translation();
+        let nearest = query_door.iter_mut().min_by(|(_, a), (_, b)| {
+            a.translation()
+                .distance_squared(item_pos)
+                .total_cmp(&b.translation().distance_squared(item_pos))
+        });
+        let Some((mut door, transform)) = nearest else {
+            continue;
+        };
+        if door.color != Some(*color_item) {
+            sfx_event.send(PlaySfxEvent::at(Sfx::DoorOpen, transform.translation().truncate()));
         }
+        door.color = Some(*color_item);
+        debug!("door color set to {:?}", color_item);
     }
 }
 
-#[derive(Component)]
-struct DoorBackgound;
-
-fn check_door_color(
+// 门的颜色变化时更新门后的背景
+fn sync_door_background(
     mut cmds: Commands,
-    mut collisions: EventReader<CollisionEvent>,
-    query_color_item: Query<Entity, (With<ColorItem>, Without<AtkNormal>)>,
-    query_bullet: Query<Entity, (With<AtkNormal>, Without<ColorItem>)>,
-    query_door: Query<&Transform, With<Door>>,
-    query_color_item_handle: Query<&DoorColorHandle>,
+    query_door: Query<(&Door, &Transform), Changed<Door>>,
     query_door_bg: Query<Entity, With<DoorBackgound>>,
-) 
-{
-    for collision_event in collisions.read() {
-        match collision_event {
-            CollisionEvent::Started(e1, e2, _) => {
-                let door = query_door.single();
-                if query_color_item.contains(*e1) && query_bullet.contains(*e2) {
-                    for entity in query_door_bg.iter() {
-                        cmds.entity(entity).despawn_recursive();
-                    }
-                    let texture = query_color_item_handle.get(*e1).unwrap().0.clone();
-                    cmds.spawn((
-                        SpriteBundle {
-                            sprite: Sprite {
-                                ..Default::default()
-                            },
-                            transform: Transform::from_xyz(door.translation.x, door.translation.y - 65., -0.5).with_scale(Vec3 { x: 0.3, y: 0.3, z: 0. }),
-                            texture,
-                            ..Default::default()
-                        },
-                        Name::new("door_open"),
-                        DoorBackgound,
-                    ));
-                    println!("Door open11111");
-                }
-                if query_color_item.contains(*e2) && query_bullet.contains(*e1) {
-                    for entity in query_door_bg.iter() {
-                        cmds.entity(entity).despawn_recursive();
-                    }
-                    let texture = query_color_item_handle.get(*e2).unwrap().0.clone();
-                    cmds.spawn((
-                        SpriteBundle {
-                            sprite: Sprite {
-                                ..Default::default()
-                            },
-                            transform: Transform::from_xyz(door.translation.x, door.translation.y - 65., -0.5).with_scale(Vec3 { x: 0.3, y: 0.3, z: 0. }),
-                            texture,
-                            ..Default::default()
-                        },
-                        Name::new("door_open"),
-                        DoorBackgound,
-                    ));
-                    println!("Door open22222");
-                }
-            }
-            _ => {},
+    asset_server: Res<AssetServer>,
+) {
+    for (door, door_transform) in query_door.iter() {
+        for entity in query_door_bg.iter() {
+            cmds.entity(entity).despawn_recursive();
+        }
+        let Some(color) = door.color else {
+            continue;
+        };
+        cmds.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(door_transform.translation.x, door_transform.translation.y - 65., -0.5).with_scale(Vec3 { x: 0.3, y: 0.3, z: 0. }),
+                texture: asset_server.load(color.door_texture()),
+                ..Default::default()
+            },
+            Name::new("door_open"),
+            DoorBackgound,
+        ));
+    }
+}
+
+// 上锁时去掉 Sensor, 玩家无法穿过
+fn sync_door_lock(
+    mut cmds: Commands,
+    mut query_door: Query<(Entity, &Door, &mut Sprite), Changed<Door>>,
+) {
+    for (entity, door, mut sprite) in query_door.iter_mut() {
+        if door.locked {
+            cmds.entity(entity).remove::<Sensor>();
+            sprite.color = Color::DARK_GRAY;
+        } else {
+            cmds.entity(entity).insert(Sensor);
+            sprite.color = Color::WHITE;
         }
     }
 }
@@ -487,60 +515,73 @@ struct EnterText;
 
 fn check_door_open(
     mut cmds: Commands,
-    mut collisions: EventReader<CollisionEvent>,
-    query1: Query<Entity, (With<Door>, Without<Player>)>,
-    query2: Query<Entity, (With<Player>, Without<Door>)>,
-    query3: Query<Entity, With<EnterText>>,
+    mut entered_events: EventReader<PlayerEnteredDoor>,
+    mut left_events: EventReader<PlayerLeftDoor>,
+    query_door: Query<&Door>,
+    query_text: Query<Entity, With<EnterText>>,
     asset_server: Res<AssetServer>,
 ) 
 {
-    for collision_event in collisions.read() {
-        match collision_event {
-            CollisionEvent::Started(e1, e2, _) => {
-                let font = asset_server.load("fonts/FiraSans-Bold.ttf");
-                let text_style = TextStyle {
-                    color: Color::BLACK,
-                    font: font.clone(),
-                    font_size: 200.0,
+    for event in entered_events.read() {
+        if query_door.get(event.door).map_or(true, |door| door.locked) {
+            continue;
+        }
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        let text_style = TextStyle {
+            color: Color::BLACK,
+            font: font.clone(),
+            font_size: 200.0,
+            ..default()
+        };
+        let text_justification = JustifyText::Center;
+        cmds.entity(event.door).with_children(|parent| {
+            parent.spawn((
+                Text2dBundle {
+                    text: Text::from_section("", text_style.clone())
+                        .with_justify(text_justification),
+                    transform: Transform::from_xyz(0., 900., 3.),
                     ..default()
-                };
-                let text_justification = JustifyText::Center;
-                if query1.contains(*e1) && query2.contains(*e2) {
-                    cmds.entity(*e1).with_children(|parent| {
-                        parent.spawn((
-                            Text2dBundle {
-                                text: Text::from_section("Enter", text_style.clone())
-                                    .with_justify(text_justification),
-                                transform: Transform::from_xyz(0., 900., 3.),
-                                ..default()
-                            },
-                            EnterText,
-                        ));
-                    });
-                    println!("Door open1");
-                }
-                if query1.contains(*e2) && query2.contains(*e1) {
-                    cmds.entity(*e2).with_children(|parent| {
-                        parent.spawn((
-                            Text2dBundle {
-                                text: Text::from_section("Enter", text_style.clone())
-                                    .with_justify(text_justification),
-                                transform: Transform::from_xyz(0., 900., 3.),
-                                ..default()
-                            },
-                        ));
-                    });
-                    println!("Door open2");
-                }
-            }
-            CollisionEvent::Stopped(e1, e2, _) => {
-                if (query1.contains(*e1) && query2.contains(*e2) || query1.contains(*e2) && query2.contains(*e1)) {
-                    for entity in query3.iter() {
-                        cmds.entity(entity).despawn_recursive();
-                    }
-                    println!("Door close");
-                }
-            },
+                },
+                EnterText,
+                LocalizedText::new("door.enter"),
+            ));
+        });
+        debug!("door {:?} opened", event.door);
+    }
+    for _ in left_events.read() {
+        for entity in query_text.iter() {
+            cmds.entity(entity).despawn_recursive();
         }
+        debug!("door closed");
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "dev")]
+fn console_level(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let target: String = console_arg(args, 0, "iid")?;
+    let (iid, center) = {
+        let handle = world
+            .query::<&Handle<LdtkProject>>()
+            .iter(world)
+            .next()
+            .cloned()
+            .ok_or("no ldtk project")?;
+        let project = world
+            .resource::<Assets<LdtkProject>>()
+            .get(&handle)
+            .ok_or("ldtk project not loaded")?;
+        let level = project
+            .iter_raw_levels()
+            .find(|level| level.iid == target || level.identifier == target)
+            .ok_or_else(|| format!("no level {}", target))?;
+        // LDtk 的 y 轴向下
+        let center = Vec2::new(
+            level.world_x as f32 + level.px_wid as f32 / 2.,
+            -(level.world_y as f32 + level.px_hei as f32 / 2.),
+        );
+        (level.iid.clone(), center)
+    };
+    world.insert_resource(LevelSelection::iid(iid.clone()));
+    crate::player::teleport_player(world, center)?;
+    Ok(format!("moved to level {}", iid))
+}
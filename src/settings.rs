@@ -0,0 +1,545 @@
+// 设置
+// Settings 保存在 settings.ron 中, 启动时读取, 修改后立即生效并写回
+// Pause 动作 (默认 Esc) 打开设置界面, 游戏中打开时同时暂停, 该按键可以在设置中修改
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::display::{next_resolution, DEFAULT_RESOLUTION};
+use crate::input::{Action, Binding, KeyBindings, WheelDirection};
+use crate::locale::{Language, Locale, LocalizedText};
+use crate::save::SaveError;
+
+pub fn settings_plugin(app: &mut App) {
+    app
+        .insert_resource(read_settings())
+        .init_resource::<SettingsMenu>()
+        .add_systems(Update, (
+            press_settings_button,
+            capture_rebind,
+            toggle_settings_menu,
+            (update_setting_values, highlight_settings_button, apply_ui_scale, persist_settings),
+        ).chain());
+}
+
+pub const SETTINGS_PATH: &str = "settings.ron";
+
+const MENU_FONT: &str = "fonts/FiraSans-Bold.ttf";
+const MENU_FONT_SIZE: f32 = 22.;
+const VOLUME_STEP: f32 = 0.1;
+const SHAKE_STEP: f32 = 0.25;
+const MAX_SCREEN_SHAKE: f32 = 2.;
+const LARGE_UI_SCALE: f32 = 1.25;
+const BUTTON_COLOR: Color = Color::rgba(1., 1., 1., 0.1);
+const BUTTON_HOVER_COLOR: Color = Color::rgba(1., 1., 1., 0.25);
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub ui_volume: f32,
+    pub fullscreen: bool,
+    // 窗口模式下的逻辑分辨率
+    pub resolution: [u32; 2],
+    pub vsync: bool,
+    // 整数倍缩放, 不足的部分加黑边
+    pub pixel_perfect: bool,
+    // 屏幕震动强度倍数
+    pub screen_shake: f32,
+    // 关闭屏幕震动和镜头缩放
+    pub reduce_motion: bool,
+    // 放大界面
+    pub large_ui: bool,
+    pub language: Language,
+    pub bindings: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+            ui_volume: 1.0,
+            fullscreen: false,
+            resolution: DEFAULT_RESOLUTION,
+            vsync: true,
+            pixel_perfect: false,
+            screen_shake: 1.0,
+            reduce_motion: false,
+            large_ui: false,
+            language: Language::default(),
+            bindings: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    // 实际使用的屏幕震动倍数
+    pub fn shake_scale(&self) -> f32 {
+        if self.reduce_motion {
+            0.
+        } else {
+            self.screen_shake.max(0.)
+        }
+    }
+}
+
+// 读取失败时使用默认设置, 不影响启动
+pub fn read_settings() -> Settings {
+    let path = Path::new(SETTINGS_PATH);
+    if !path.exists() {
+        return Settings::default();
+    }
+    let settings = fs::read_to_string(path)
+        .map_err(SaveError::from)
+        .and_then(|text| ron::from_str(&text).map_err(SaveError::Deserialize));
+    settings.unwrap_or_else(|e| {
+        warn!("failed to read {} ({}), using defaults", SETTINGS_PATH, e);
+        Settings::default()
+    })
+}
+
+// 和存档一样先写临时文件再 rename
+pub fn write_settings(settings: &Settings) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default()).map_err(SaveError::Serialize)?;
+    let path = Path::new(SETTINGS_PATH);
+    let tmp_path = path.with_extension("ron.tmp");
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// 设置界面的状态, rebinding 为正在等待新按键的动作
+#[derive(Resource, Debug, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    pub rebinding: Option<Action>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SettingField {
+    MusicVolume,
+    SfxVolume,
+    UiVolume,
+    Resolution,
+    Fullscreen,
+    Vsync,
+    PixelPerfect,
+    ScreenShake,
+    ReduceMotion,
+    LargeUi,
+    Language,
+}
+
+impl SettingField {
+    const ALL: [SettingField; 11] = [
+        SettingField::MusicVolume,
+        SettingField::SfxVolume,
+        SettingField::UiVolume,
+        SettingField::Resolution,
+        SettingField::Fullscreen,
+        SettingField::Vsync,
+        SettingField::PixelPerfect,
+        SettingField::ScreenShake,
+        SettingField::ReduceMotion,
+        SettingField::LargeUi,
+        SettingField::Language,
+    ];
+
+    // 名字的 key
+    fn label(self) -> &'static str {
+        match self {
+            SettingField::MusicVolume => "settings.music",
+            SettingField::SfxVolume => "settings.sound",
+            SettingField::UiVolume => "settings.interface",
+            SettingField::Resolution => "settings.resolution",
+            SettingField::Fullscreen => "settings.fullscreen",
+            SettingField::Vsync => "settings.vsync",
+            SettingField::PixelPerfect => "settings.pixel_perfect",
+            SettingField::ScreenShake => "settings.screen_shake",
+            SettingField::ReduceMotion => "settings.reduce_motion",
+            SettingField::LargeUi => "settings.large_ui",
+            SettingField::Language => "settings.language",
+        }
+    }
+
+    fn value(self, settings: &Settings, locale: &Locale) -> String {
+        let percent = |v: f32| format!("{}%", (v * 100.).round());
+        let toggle = |v: bool| locale.get(if v { "settings.on" } else { "settings.off" });
+        match self {
+            SettingField::MusicVolume => percent(settings.music_volume),
+            SettingField::SfxVolume => percent(settings.sfx_volume),
+            SettingField::UiVolume => percent(settings.ui_volume),
+            SettingField::Resolution => format!("{}x{}", settings.resolution[0], settings.resolution[1]),
+            SettingField::Fullscreen => toggle(settings.fullscreen),
+            SettingField::Vsync => toggle(settings.vsync),
+            SettingField::PixelPerfect => toggle(settings.pixel_perfect),
+            SettingField::ScreenShake => percent(settings.screen_shake),
+            SettingField::ReduceMotion => toggle(settings.reduce_motion),
+            SettingField::LargeUi => toggle(settings.large_ui),
+            // 设置的语言无法显示时实际使用的是英文
+            SettingField::Language => locale.get(locale.language.key()),
+        }
+    }
+
+    // step 为 1 或 -1, 开关类设置直接切换
+    fn adjust(self, settings: &mut Settings, locale: &Locale, step: i32) {
+        let stepped = |v: f32, delta: f32, max: f32| (((v / delta).round() + step as f32) * delta).clamp(0., max);
+        match self {
+            SettingField::MusicVolume => settings.music_volume = stepped(settings.music_volume, VOLUME_STEP, 1.),
+            SettingField::SfxVolume => settings.sfx_volume = stepped(settings.sfx_volume, VOLUME_STEP, 1.),
+            SettingField::UiVolume => settings.ui_volume = stepped(settings.ui_volume, VOLUME_STEP, 1.),
+            SettingField::Resolution => settings.resolution = next_resolution(settings.resolution, step),
+            SettingField::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            SettingField::Vsync => settings.vsync = !settings.vsync,
+            SettingField::PixelPerfect => settings.pixel_perfect = !settings.pixel_perfect,
+            SettingField::ScreenShake => settings.screen_shake = stepped(settings.screen_shake, SHAKE_STEP, MAX_SCREEN_SHAKE),
+            SettingField::ReduceMotion => settings.reduce_motion = !settings.reduce_motion,
+            SettingField::LargeUi => settings.large_ui = !settings.large_ui,
+            SettingField::Language => settings.language = locale.next_language(locale.language, step),
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum SettingsButton {
+    Adjust(SettingField, i32),
+    Rebind(Action),
+    Close,
+}
+
+// 显示当前值的文字
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+enum SettingValue {
+    Field(SettingField),
+    Binding(Action),
+}
+
+#[derive(Component)]
+struct SettingsMenuRoot;
+
+fn spawn_button(parent: &mut ChildBuilder, button: SettingsButton, label: &str, font: &Handle<Font>) {
+    parent.spawn((
+        ButtonBundle {
+            style: Style {
+                padding: UiRect::axes(Val::Px(10.), Val::Px(2.)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            background_color: BUTTON_COLOR.into(),
+            ..default()
+        },
+        button,
+    )).with_children(|parent| {
+        parent.spawn((TextBundle::from_section("", menu_text_style(font)), LocalizedText::new(label)));
+    });
+}
+
+fn menu_text_style(font: &Handle<Font>) -> TextStyle {
+    TextStyle {
+        font: font.clone(),
+        font_size: MENU_FONT_SIZE,
+        color: Color::WHITE,
+    }
+}
+
+// 一行设置: 名字, 可选的左右按钮和当前值
+fn spawn_row(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: &str,
+    value: SettingValue,
+    buttons: Option<(SettingsButton, SettingsButton)>,
+) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::SpaceBetween,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(12.),
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn((TextBundle::from_section("", menu_text_style(font)), LocalizedText::new(label)));
+        parent.spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        }).with_children(|parent| {
+            match (value, buttons) {
+                (SettingValue::Binding(action), _) => {
+                    parent.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                min_width: Val::Px(120.),
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            background_color: BUTTON_COLOR.into(),
+                            ..default()
+                        },
+                        SettingsButton::Rebind(action),
+                    )).with_children(|parent| {
+                        parent.spawn((TextBundle::from_section("", menu_text_style(font)), value));
+                    });
+                }
+                (_, Some((prev, next))) => {
+                    spawn_button(parent, prev, "<", font);
+                    parent.spawn((TextBundle::from_section("", menu_text_style(font)), value));
+                    spawn_button(parent, next, ">", font);
+                }
+                (_, None) => {
+                    parent.spawn((TextBundle::from_section("", menu_text_style(font)), value));
+                }
+            }
+        });
+    });
+}
+
+fn spawn_settings_menu(cmds: &mut Commands, asset_server: &AssetServer) {
+    let font = asset_server.load(MENU_FONT);
+    cmds.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.7).into(),
+            z_index: ZIndex::Global(100),
+            ..default()
+        },
+        SettingsMenuRoot,
+        Name::new("settings_menu"),
+    )).with_children(|parent| {
+        parent.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(420.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.),
+                padding: UiRect::all(Val::Px(16.)),
+                ..default()
+            },
+            background_color: Color::rgba(0.1, 0.1, 0.1, 0.9).into(),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((TextBundle::from_section("", menu_text_style(&font)), LocalizedText::new("settings.title")));
+            for field in SettingField::ALL {
+                spawn_row(
+                    parent,
+                    &font,
+                    field.label(),
+                    SettingValue::Field(field),
+                    Some((SettingsButton::Adjust(field, -1), SettingsButton::Adjust(field, 1))),
+                );
+            }
+            parent.spawn((TextBundle::from_section("", menu_text_style(&font)), LocalizedText::new("settings.controls")));
+            for action in Action::ALL {
+                spawn_row(parent, &font, &format!("action.{:?}", action), SettingValue::Binding(action), None);
+            }
+            spawn_button(parent, SettingsButton::Close, "settings.back", &font);
+        });
+    });
+}
+
+// 游戏中打开设置时暂停时间和物理
+fn set_paused(time: &mut Time<Virtual>, rapier_config: &mut RapierConfiguration, paused: bool) {
+    if paused {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+    rapier_config.physics_pipeline_active = !paused;
+}
+
+fn close_settings_menu(
+    cmds: &mut Commands,
+    menu: &mut SettingsMenu,
+    query_root: &Query<Entity, With<SettingsMenuRoot>>,
+) {
+    menu.open = false;
+    menu.rebinding = None;
+    for entity in query_root.iter() {
+        cmds.entity(entity).despawn_recursive();
+    }
+}
+
+fn toggle_settings_menu(
+    mut cmds: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    wheel: Res<ButtonInput<WheelDirection>>,
+    settings: Res<Settings>,
+    asset_server: Res<AssetServer>,
+    mut menu: ResMut<SettingsMenu>,
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    query_root: Query<Entity, With<SettingsMenuRoot>>,
+) {
+    // 设置界面打开时 ActionInput 不响应, 这里直接读取按键
+    let pressed = settings
+        .bindings
+        .get(Action::Pause)
+        .is_some_and(|binding| binding.just_pressed(&keys, &mouse, &wheel));
+    if !pressed {
+        return;
+    }
+    if menu.open {
+        close_settings_menu(&mut cmds, &mut menu, &query_root);
+        set_paused(&mut time, &mut rapier_config, false);
+    } else {
+        menu.open = true;
+        spawn_settings_menu(&mut cmds, &asset_server);
+        set_paused(&mut time, &mut rapier_config, true);
+    }
+}
+
+// 等待新按键时, 下一个按下的键或鼠标按钮成为该动作的按键, Esc 取消
+// 开始等待的那一帧忽略输入, 否则点击按钮的鼠标键会直接被绑定
+// 绑定后清除该按键, 以免新的 Pause 按键在同一帧关闭设置界面
+fn capture_rebind(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+    mut wheel: ResMut<ButtonInput<WheelDirection>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut armed: Local<bool>,
+) {
+    let Some(action) = menu.rebinding else {
+        *armed = false;
+        return;
+    };
+    if !*armed {
+        *armed = true;
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.clear_just_pressed(KeyCode::Escape);
+        menu.rebinding = None;
+        return;
+    }
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| mouse.get_just_pressed().next().map(|button| Binding::Mouse(*button)))
+        .or_else(|| wheel.get_just_pressed().next().map(|direction| Binding::Wheel(*direction)));
+    if let Some(binding) = binding {
+        match binding {
+            Binding::Key(key) => keys.clear_just_pressed(key),
+            Binding::Mouse(button) => mouse.clear_just_pressed(button),
+            Binding::Wheel(direction) => wheel.clear_just_pressed(direction),
+        }
+        settings.bindings.set(action, binding);
+        menu.rebinding = None;
+    }
+}
+
+fn press_settings_button(
+    mut cmds: Commands,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    locale: Res<Locale>,
+    mut time: ResMut<Time<Virtual>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    query_button: Query<(&Interaction, &SettingsButton), Changed<Interaction>>,
+    query_root: Query<Entity, With<SettingsMenuRoot>>,
+) {
+    if menu.rebinding.is_some() {
+        return;
+    }
+    for (interaction, button) in query_button.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            SettingsButton::Adjust(field, step) => field.adjust(&mut settings, &locale, step),
+            SettingsButton::Rebind(action) => menu.rebinding = Some(action),
+            SettingsButton::Close => {
+                close_settings_menu(&mut cmds, &mut menu, &query_root);
+                set_paused(&mut time, &mut rapier_config, false);
+                return;
+            }
+        }
+    }
+}
+
+fn update_setting_values(
+    settings: Res<Settings>,
+    menu: Res<SettingsMenu>,
+    locale: Res<Locale>,
+    mut query_value: Query<(Ref<SettingValue>, &mut Text)>,
+) {
+    let changed = settings.is_changed() || menu.is_changed() || locale.is_changed();
+    for (value, mut text) in query_value.iter_mut() {
+        if !changed && !value.is_added() {
+            continue;
+        }
+        text.sections[0].value = match *value {
+            SettingValue::Field(field) => field.value(&settings, &locale),
+            SettingValue::Binding(action) if menu.rebinding == Some(action) => "...".to_string(),
+            SettingValue::Binding(action) => settings
+                .bindings
+                .get(action)
+                .map(|binding| binding.label())
+                .unwrap_or_default(),
+        };
+    }
+}
+
+fn highlight_settings_button(
+    mut query_button: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<SettingsButton>)>,
+) {
+    for (interaction, mut color) in query_button.iter_mut() {
+        *color = match interaction {
+            Interaction::Hovered | Interaction::Pressed => BUTTON_HOVER_COLOR.into(),
+            Interaction::None => BUTTON_COLOR.into(),
+        };
+    }
+}
+
+fn apply_ui_scale(settings: Res<Settings>, mut ui_scale: ResMut<UiScale>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let scale = if settings.large_ui { LARGE_UI_SCALE } else { 1. };
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+// 和上次写入的内容不同才写文件
+fn persist_settings(settings: Res<Settings>, mut saved: Local<Option<Settings>>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let Some(previous) = saved.as_ref() else {
+        // 启动时读到的设置不需要写回
+        *saved = Some(settings.clone());
+        return;
+    };
+    if previous == settings.as_ref() {
+        return;
+    }
+    match write_settings(&settings) {
+        Ok(()) => *saved = Some(settings.clone()),
+        Err(e) => error!("failed to write {}: {}", SETTINGS_PATH, e),
+    }
+}
@@ -0,0 +1,843 @@
+// 运行时快照, 用于快速存档和调试时回到指定时刻
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::boss::{set_boss_fight, spawn_boss, Boss, BossFight, Charge};
+use crate::comm::Health;
+use crate::enemy::{Enemy, EnemyBrain, EnemyKind, EnemyState, EnemyStats, Patrol, PatrolMode};
+use crate::global::Score;
+use crate::player::{Climber, Player, PlayerMotion};
+use crate::roulette::{Roulette, RouletteChangedEvent, RouletteRng, RouletteSlot};
+use crate::save::{SaveError, SAVE_DIR};
+use crate::scene::{ColorItem, Door};
+use crate::spawner::{spawn_enemy, Difficulty, RunProgress, SpawnedBy, Spawner};
+
+pub fn snapshot_plugin(app: &mut App) {
+    app
+        .register_type::<SnapshotControl>()
+        .init_resource::<SnapshotControl>()
+        .add_event::<SnapshotEvent>()
+        .add_systems(Update, (
+            snapshot_control,
+            handle_snapshot_events,
+        ).chain());
+
+    #[cfg(feature = "dev")]
+    {
+        app.add_systems(Update, test_snapshot.before(snapshot_control));
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RunSnapshot {
+    pub level: Option<String>,
+    pub player: Option<PlayerSnapshot>,
+    pub roulette: RouletteSnapshot,
+    pub enemies: Vec<EnemySnapshot>,
+    pub doors: Vec<DoorSnapshot>,
+    pub score: u32,
+    // 转盘随机数状态, 恢复后抽取结果与快照时一致
+    #[serde(default)]
+    pub rng_state: Option<u64>,
+    #[serde(default)]
+    pub spawners: Vec<SpawnerSnapshot>,
+    #[serde(default)]
+    pub boss_fight: Option<BossFightSnapshot>,
+    #[serde(default)]
+    pub difficulty: Option<f32>,
+    #[serde(default)]
+    pub run_progress: Option<(f32, Option<f32>)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PlayerSnapshot {
+    pub translation: [f32; 3],
+    pub linvel: [f32; 2],
+    // 玩家由角色控制器移动, 跳跃和下落靠这两个值
+    #[serde(default)]
+    pub vertical: f32,
+    #[serde(default)]
+    pub grounded_timer: f32,
+    #[serde(default)]
+    pub climbing: bool,
+    #[serde(default)]
+    pub health: Option<Health>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RouletteSnapshot {
+    pub items: Vec<RouletteSlot>,
+    pub cur_index: usize,
+    #[serde(default)]
+    pub pending: Option<RouletteSlot>,
+}
+
+// 只记录单次计时器的进度
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimerSnapshot {
+    pub duration: f32,
+    pub elapsed: f32,
+}
+
+impl TimerSnapshot {
+    fn capture(timer: &Timer) -> Self {
+        Self {
+            duration: timer.duration().as_secs_f32(),
+            elapsed: timer.elapsed_secs(),
+        }
+    }
+
+    // tick 一次零时长, 让 finished 与进度一致
+    fn to_timer(self) -> Timer {
+        let mut timer = Timer::from_seconds(self.duration, TimerMode::Once);
+        timer.set_elapsed(Duration::from_secs_f32(self.elapsed));
+        timer.tick(Duration::ZERO);
+        timer
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BrainSnapshot {
+    pub state: EnemyState,
+    pub attack_timer: TimerSnapshot,
+    pub stun_timer: TimerSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PatrolSnapshot {
+    pub points: Vec<[f32; 2]>,
+    pub waits: Vec<f32>,
+    pub speeds: Vec<f32>,
+    pub mode: PatrolMode,
+    pub easing: bool,
+    pub index: usize,
+    pub forward: bool,
+    pub wait: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ChargeSnapshot {
+    pub velocity: [f32; 2],
+    pub timer: TimerSnapshot,
+    pub hit: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BossSnapshot {
+    pub thresholds: Vec<f32>,
+    pub arena: [f32; 2],
+    pub phase: usize,
+    pub origin: Option<[f32; 2]>,
+    pub pattern: usize,
+    pub attack_timer: TimerSnapshot,
+    pub charge: Option<ChargeSnapshot>,
+}
+
+// LDtk 中放置的敌人用 iid 对应, 运行时刷出的敌人没有 iid, 恢复时重新生成
+// 快照之后被消灭的敌人也会重新生成, 快照之后才出现的敌人被移除
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EnemySnapshot {
+    pub iid: Option<String>,
+    // 所在关卡, 重新生成时放到该关卡下
+    pub level: Option<String>,
+    // 世界坐标
+    pub translation: [f32; 3],
+    pub linvel: [f32; 2],
+    pub kind: Option<EnemyKind>,
+    pub stats: Option<EnemyStats>,
+    pub health: Option<Health>,
+    pub brain: Option<BrainSnapshot>,
+    pub patrol: Option<PatrolSnapshot>,
+    pub boss: Option<BossSnapshot>,
+    // 刷出该敌人的刷怪点或 Boss 的 iid
+    pub spawned_by: Option<String>,
+}
+
+// 门用 Name 对应
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DoorSnapshot {
+    pub name: String,
+    pub color: Option<ColorItem>,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+// 刷怪点用 iid 对应
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SpawnerSnapshot {
+    pub iid: String,
+    pub wave: u32,
+    pub active: bool,
+    pub finished: bool,
+    pub step: usize,
+    pub spawned: u32,
+    pub timer: TimerSnapshot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BossFightSnapshot {
+    pub boss: String,
+    pub arena: [f32; 4],
+}
+
+fn vec2(v: [f32; 2]) -> Vec2 {
+    Vec2::from_array(v)
+}
+
+fn iid_of(world: &World, entity: Entity) -> Option<String> {
+    world.get::<EntityIid>(entity).map(|iid| iid.to_string())
+}
+
+// 沿父节点向上找到所在的关卡
+fn level_of(world: &World, entity: Entity) -> Option<String> {
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        if let Some(level) = world.get::<LevelIid>(entity) {
+            return Some(level.to_string());
+        }
+        current = world.get::<Parent>(entity).map(Parent::get);
+    }
+    None
+}
+
+fn capture_enemy(world: &World, entity: Entity) -> EnemySnapshot {
+    let translation = world
+        .get::<GlobalTransform>(entity)
+        .map(|transform| transform.translation())
+        .unwrap_or_default();
+    let patrol = world.get::<Patrol>(entity).map(|patrol| PatrolSnapshot {
+        points: patrol.points.iter().map(|p| p.to_array()).collect(),
+        waits: patrol.waits.clone(),
+        speeds: patrol.speeds.clone(),
+        mode: patrol.mode,
+        easing: patrol.easing,
+        index: patrol.index,
+        forward: patrol.forward,
+        wait: patrol.wait,
+    });
+    let boss = world.get::<Boss>(entity).map(|boss| BossSnapshot {
+        thresholds: boss.thresholds(),
+        arena: boss.arena.to_array(),
+        phase: boss.phase,
+        origin: boss.origin.map(|origin| origin.to_array()),
+        pattern: boss.pattern,
+        attack_timer: TimerSnapshot::capture(&boss.attack_timer),
+        charge: boss.charge.as_ref().map(|charge| ChargeSnapshot {
+            velocity: charge.velocity.to_array(),
+            timer: TimerSnapshot::capture(&charge.timer),
+            hit: charge.hit,
+        }),
+    });
+    EnemySnapshot {
+        iid: iid_of(world, entity),
+        level: level_of(world, entity),
+        translation: translation.to_array(),
+        linvel: world.get::<Velocity>(entity).map(|v| v.linvel).unwrap_or_default().to_array(),
+        kind: world.get::<EnemyKind>(entity).copied(),
+        stats: world.get::<EnemyStats>(entity).cloned(),
+        health: world.get::<Health>(entity).copied(),
+        brain: world.get::<EnemyBrain>(entity).map(|brain| BrainSnapshot {
+            state: brain.state,
+            attack_timer: TimerSnapshot::capture(&brain.attack_timer),
+            stun_timer: TimerSnapshot::capture(&brain.stun_timer),
+        }),
+        patrol,
+        boss,
+        spawned_by: world.get::<SpawnedBy>(entity).and_then(|s| iid_of(world, s.0)),
+    }
+}
+
+// 记录当前世界状态
+pub fn capture(world: &mut World) -> RunSnapshot {
+    let level = match world.get_resource::<LevelSelection>() {
+        Some(LevelSelection::Iid(iid)) => Some(iid.to_string()),
+        _ => None,
+    };
+
+    let player = world
+        .query_filtered::<(&Transform, Option<&Velocity>, Option<&PlayerMotion>, Option<&Climber>, Option<&Health>), With<Player>>()
+        .iter(world)
+        .next()
+        .map(|(transform, velocity, motion, climber, health)| {
+            let motion = motion.copied().unwrap_or_default();
+            PlayerSnapshot {
+                translation: transform.translation.to_array(),
+                linvel: velocity.map(|v| v.linvel).unwrap_or_default().to_array(),
+                vertical: motion.vertical,
+                grounded_timer: motion.grounded_timer,
+                climbing: climber.is_some_and(|c| c.climbing),
+                health: health.copied(),
+            }
+        });
+
+    let roulette = world
+        .query::<&Roulette>()
+        .iter(world)
+        .next()
+        .map(|r| RouletteSnapshot {
+            items: r.get_all_items().clone(),
+            cur_index: r.get_cur_index(),
+            pending: r.get_pending().cloned(),
+        })
+        .unwrap_or_default();
+
+    let enemy_entities: Vec<Entity> = world.query_filtered::<Entity, With<Enemy>>().iter(world).collect();
+    let mut enemies: Vec<EnemySnapshot> = enemy_entities.into_iter().map(|e| capture_enemy(world, e)).collect();
+    // 查询顺序不固定, 排序后快照可以直接比较
+    enemies.sort_by(|a, b| {
+        (&a.iid, a.translation.map(f32::to_bits)).cmp(&(&b.iid, b.translation.map(f32::to_bits)))
+    });
+
+    let doors = world
+        .query::<(&Name, &Door)>()
+        .iter(world)
+        .map(|(name, door)| DoorSnapshot {
+            name: name.to_string(),
+            color: door.color,
+            locked: door.locked,
+        })
+        .collect();
+
+    let mut spawners: Vec<SpawnerSnapshot> = world
+        .query::<(&EntityIid, &Spawner)>()
+        .iter(world)
+        .map(|(iid, spawner)| SpawnerSnapshot {
+            iid: iid.to_string(),
+            wave: spawner.wave,
+            active: spawner.active,
+            finished: spawner.finished,
+            step: spawner.step,
+            spawned: spawner.spawned,
+            timer: TimerSnapshot::capture(&spawner.timer),
+        })
+        .collect();
+    spawners.sort_by(|a, b| a.iid.cmp(&b.iid));
+
+    let boss_fight = world.get_resource::<BossFight>().and_then(|fight| {
+        Some(BossFightSnapshot {
+            boss: iid_of(world, fight.boss)?,
+            arena: [fight.arena.min.x, fight.arena.min.y, fight.arena.max.x, fight.arena.max.y],
+        })
+    });
+
+    let score = world.get_resource::<Score>().map(|s| s.0).unwrap_or_default();
+    let rng_state = world.get_resource::<RouletteRng>().map(|rng| rng.state());
+    let difficulty = world.get_resource::<Difficulty>().map(|d| d.0);
+    let run_progress = world
+        .get_resource::<RunProgress>()
+        .map(|progress| (progress.elapsed, progress.start_height));
+
+    RunSnapshot {
+        level,
+        player,
+        roulette,
+        enemies,
+        doors,
+        score,
+        rng_state,
+        spawners,
+        boss_fight,
+        difficulty,
+        run_progress,
+    }
+}
+
+fn restore_boss(boss: &mut Boss, state: &BossSnapshot) {
+    boss.phase = state.phase;
+    boss.origin = state.origin.map(vec2);
+    boss.pattern = state.pattern;
+    boss.attack_timer = state.attack_timer.to_timer();
+    boss.charge = state.charge.as_ref().map(|charge| Charge {
+        velocity: vec2(charge.velocity),
+        timer: charge.timer.to_timer(),
+        hit: charge.hit,
+    });
+}
+
+fn restore_patrol(patrol: &mut Patrol, state: &PatrolSnapshot) {
+    *patrol = Patrol {
+        points: state.points.iter().copied().map(vec2).collect(),
+        waits: state.waits.clone(),
+        speeds: state.speeds.clone(),
+        mode: state.mode,
+        easing: state.easing,
+        index: state.index,
+        forward: state.forward,
+        wait: state.wait,
+    };
+}
+
+// 把已有敌人的状态设为快照中的值
+fn restore_enemy(world: &mut World, entity: Entity, state: &EnemySnapshot, owners: &HashMap<String, Entity>) {
+    let target = Vec3::from_array(state.translation);
+    let global = world.get::<GlobalTransform>(entity).copied().unwrap_or_default();
+    let offset = target - global.translation();
+    let mut entity_mut = world.entity_mut(entity);
+    if let Some(mut transform) = entity_mut.get_mut::<Transform>() {
+        transform.translation += offset;
+    }
+    // 同一帧再次读取时位置也正确
+    if let Some(mut global) = entity_mut.get_mut::<GlobalTransform>() {
+        *global = global.compute_transform().with_translation(target).into();
+    }
+    if let Some(mut velocity) = entity_mut.get_mut::<Velocity>() {
+        velocity.linvel = vec2(state.linvel);
+    }
+    if let Some(stats) = &state.stats {
+        entity_mut.insert(stats.clone());
+    }
+    if let Some(health) = state.health {
+        entity_mut.insert(health);
+    }
+    if let (Some(brain), Some(state)) = (entity_mut.get_mut::<EnemyBrain>(), &state.brain) {
+        let brain = brain.into_inner();
+        brain.state = state.state;
+        brain.attack_timer = state.attack_timer.to_timer();
+        brain.stun_timer = state.stun_timer.to_timer();
+    }
+    if let Some(patrol) = &state.patrol {
+        match entity_mut.get_mut::<Patrol>() {
+            Some(mut current) => restore_patrol(&mut current, patrol),
+            None => {
+                let mut current = Patrol::default();
+                restore_patrol(&mut current, patrol);
+                entity_mut.insert(current);
+            }
+        }
+    }
+    if let (Some(mut boss), Some(state)) = (entity_mut.get_mut::<Boss>(), &state.boss) {
+        restore_boss(&mut boss, state);
+    }
+    match state.spawned_by.as_ref().and_then(|iid| owners.get(iid)) {
+        Some(owner) => {
+            entity_mut.insert(SpawnedBy(*owner));
+        }
+        None => {
+            entity_mut.remove::<SpawnedBy>();
+        }
+    }
+}
+
+// 重新生成快照中有但世界中已经没有的敌人, 放到所在关卡下
+fn respawn_enemy(world: &mut World, state: &EnemySnapshot, levels: &HashMap<String, (Entity, Vec3)>) -> Entity {
+    let target = Vec3::from_array(state.translation);
+    let level = state.level.as_ref().and_then(|level| levels.get(level)).copied();
+    let translation = level.map_or(target, |(_, origin)| target - origin);
+
+    let mut queue = bevy::ecs::system::CommandQueue::default();
+    let mut cmds = Commands::new(&mut queue, world);
+    let entity = match &state.boss {
+        Some(boss) => spawn_boss(
+            &mut cmds,
+            translation,
+            Boss::new(&boss.thresholds, vec2(boss.arena)),
+            state.health.unwrap_or_default(),
+        ),
+        None => spawn_enemy(
+            &mut cmds,
+            state.kind.unwrap_or_default(),
+            translation,
+            state.health.map_or(Health::default().max, |h| h.max),
+            state.stats.as_ref().map(|stats| stats.speed),
+        ),
+    };
+    if let Some(iid) = &state.iid {
+        cmds.entity(entity).insert(EntityIid::new(iid.clone()));
+    }
+    if let Some((level, _)) = level {
+        cmds.entity(level).add_child(entity);
+    }
+    queue.apply(world);
+    world.entity_mut(entity).insert(GlobalTransform::from_translation(target));
+    entity
+}
+
+// 把世界恢复到快照时的状态
+pub fn restore(world: &mut World, snapshot: &RunSnapshot) {
+    if let Some(level) = &snapshot.level {
+        world.insert_resource(LevelSelection::iid(level.clone()));
+    }
+
+    if let Some(player) = &snapshot.player {
+        let mut query = world.query_filtered::<(
+            &mut Transform,
+            Option<&mut Velocity>,
+            Option<&mut PlayerMotion>,
+            Option<&mut Climber>,
+            Option<&mut Health>,
+        ), With<Player>>();
+        for (mut transform, velocity, motion, climber, health) in query.iter_mut(world) {
+            transform.translation = Vec3::from_array(player.translation);
+            if let Some(mut velocity) = velocity {
+                velocity.linvel = vec2(player.linvel);
+            }
+            if let Some(mut motion) = motion {
+                motion.vertical = player.vertical;
+                motion.grounded_timer = player.grounded_timer;
+            }
+            if let Some(mut climber) = climber {
+                climber.climbing = player.climbing;
+            }
+            if let (Some(mut health), Some(state)) = (health, player.health) {
+                *health = state;
+            }
+        }
+    }
+
+    let mut query = world.query::<&mut Roulette>();
+    for mut roulette in query.iter_mut(world) {
+        roulette.load(
+            snapshot.roulette.items.clone(),
+            snapshot.roulette.cur_index,
+            snapshot.roulette.pending.clone(),
+        );
+    }
+    world.send_event(RouletteChangedEvent::Reset);
+
+    // 先处理敌人以外的 LDtk 实体, 刷怪点和 Boss 的 iid 用于恢复 SpawnedBy
+    let levels: HashMap<String, (Entity, Vec3)> = world
+        .query::<(Entity, &LevelIid, Option<&GlobalTransform>)>()
+        .iter(world)
+        .map(|(entity, iid, transform)| (iid.to_string(), (entity, transform.map(|t| t.translation()).unwrap_or_default())))
+        .collect();
+
+    let current: Vec<(Entity, Option<String>)> = world
+        .query_filtered::<(Entity, Option<&EntityIid>), With<Enemy>>()
+        .iter(world)
+        .map(|(entity, iid)| (entity, iid.map(|iid| iid.to_string())))
+        .collect();
+    let mut kept: HashMap<String, Entity> = HashMap::new();
+    for (entity, iid) in current {
+        match iid.filter(|iid| snapshot.enemies.iter().any(|e| e.iid.as_ref() == Some(iid))) {
+            Some(iid) => {
+                kept.insert(iid, entity);
+            }
+            // 运行时刷出的敌人全部按快照重新生成
+            None => {
+                world.entity_mut(entity).despawn_recursive();
+            }
+        }
+    }
+    let mut enemies = vec![];
+    for state in &snapshot.enemies {
+        let entity = match state.iid.as_ref().and_then(|iid| kept.get(iid)) {
+            Some(entity) => *entity,
+            None => respawn_enemy(world, state, &levels),
+        };
+        enemies.push((entity, state));
+    }
+    let owners: HashMap<String, Entity> = world
+        .query::<(Entity, &EntityIid)>()
+        .iter(world)
+        .map(|(entity, iid)| (iid.to_string(), entity))
+        .collect();
+    for (entity, state) in enemies {
+        restore_enemy(world, entity, state, &owners);
+    }
+
+    let mut query = world.query::<(&EntityIid, &mut Spawner)>();
+    for (iid, mut spawner) in query.iter_mut(world) {
+        let iid = iid.to_string();
+        if let Some(state) = snapshot.spawners.iter().find(|s| s.iid == iid) {
+            spawner.wave = state.wave;
+            spawner.active = state.active;
+            spawner.finished = state.finished;
+            spawner.step = state.step;
+            spawner.spawned = state.spawned;
+            spawner.timer = state.timer.to_timer();
+        }
+    }
+
+    let fight = snapshot.boss_fight.as_ref().and_then(|fight| {
+        Some(BossFight {
+            boss: *owners.get(&fight.boss)?,
+            arena: Rect::new(fight.arena[0], fight.arena[1], fight.arena[2], fight.arena[3]),
+        })
+    });
+    set_boss_fight(world, fight);
+
+    let mut query = world.query::<(&Name, &mut Door)>();
+    for (name, mut door) in query.iter_mut(world) {
+        if let Some(state) = snapshot.doors.iter().find(|d| d.name == name.as_str()) {
+            door.color = state.color;
+            door.locked = state.locked;
+        }
+    }
+
+    if let Some(mut score) = world.get_resource_mut::<Score>() {
+        score.0 = snapshot.score;
+    }
+
+    if let (Some(state), Some(mut rng)) = (snapshot.rng_state, world.get_resource_mut::<RouletteRng>()) {
+        rng.set_state(state);
+    }
+
+    if let (Some(value), Some(mut difficulty)) = (snapshot.difficulty, world.get_resource_mut::<Difficulty>()) {
+        difficulty.0 = value;
+    }
+    if let (Some((elapsed, start_height)), Some(mut progress)) = (snapshot.run_progress, world.get_resource_mut::<RunProgress>()) {
+        progress.elapsed = elapsed;
+        progress.start_height = start_height;
+    }
+}
+
+pub fn write_snapshot(path: &Path, snapshot: &RunSnapshot) -> Result<(), SaveError> {
+    let text = ron::ser::to_string_pretty(snapshot, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Serialize)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)?;
+    Ok(())
+}
+
+pub fn read_snapshot(path: &Path) -> Result<RunSnapshot, SaveError> {
+    ron::from_str(&fs::read_to_string(path)?).map_err(SaveError::Deserialize)
+}
+
+#[derive(Event, Debug)]
+pub enum SnapshotEvent {
+    Capture(PathBuf),
+    Restore(PathBuf),
+}
+
+// 在编辑器的资源面板里勾选 capture / restore 触发快照
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct SnapshotControl {
+    pub path: String,
+    pub capture: bool,
+    pub restore: bool,
+}
+
+impl Default for SnapshotControl {
+    fn default() -> Self {
+        Self {
+            path: format!("{}/snapshot.ron", SAVE_DIR),
+            capture: false,
+            restore: false,
+        }
+    }
+}
+
+fn snapshot_control(
+    mut control: ResMut<SnapshotControl>,
+    mut events: EventWriter<SnapshotEvent>,
+) {
+    if control.capture {
+        control.capture = false;
+        events.send(SnapshotEvent::Capture(PathBuf::from(&control.path)));
+    }
+    if control.restore {
+        control.restore = false;
+        events.send(SnapshotEvent::Restore(PathBuf::from(&control.path)));
+    }
+}
+
+fn handle_snapshot_events(world: &mut World) {
+    let events: Vec<SnapshotEvent> = world.resource_mut::<Events<SnapshotEvent>>().drain().collect();
+    for event in events {
+        let _span = info_span!("snapshot", event = ?event).entered();
+        match event {
+            SnapshotEvent::Capture(path) => {
+                let snapshot = capture(world);
+                match write_snapshot(&path, &snapshot) {
+                    Ok(()) => info!("snapshot written to {}", path.display()),
+                    Err(e) => error!("failed to write snapshot {}: {}", path.display(), e),
+                }
+            }
+            SnapshotEvent::Restore(path) => match read_snapshot(&path) {
+                Ok(snapshot) => restore(world, &snapshot),
+                Err(e) => error!("failed to read snapshot {}: {}", path.display(), e),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "dev")]
+pub fn test_snapshot(
+    mut control: ResMut<SnapshotControl>,
+    input: Res<ButtonInput<KeyCode>>,
+) {
+    if input.just_pressed(KeyCode::F5) {
+        control.capture = true;
+    }
+    if input.just_pressed(KeyCode::F9) {
+        control.restore = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Events<RouletteChangedEvent>>();
+        world.insert_resource(Score(120));
+        world.insert_resource(RouletteRng::new(7));
+        world.insert_resource(LevelSelection::iid("level_a"));
+        world.spawn((
+            Player,
+            Transform::from_xyz(10., 20., 5.),
+            Velocity::linear(Vec2::new(1., -2.)),
+            PlayerMotion {
+                vertical: 1.5,
+                grounded_timer: 0.25,
+            },
+        ));
+        let mut roulette = Roulette::new();
+        roulette.add_item(1, 3);
+        roulette.add_item(2, 0);
+        roulette.next();
+        roulette.start_spin(0, 1);
+        world.spawn(roulette);
+        let mut patrol = Patrol::new(vec![Vec2::ZERO, Vec2::X * 32., Vec2::X * 64.]);
+        patrol.index = 2;
+        patrol.forward = false;
+        patrol.wait = 0.5;
+        let mut brain = EnemyBrain::default();
+        brain.stun(0.4);
+        world.spawn((
+            Enemy,
+            EntityIid::new("enemy_a"),
+            Transform::from_xyz(30., 0., 0.),
+            GlobalTransform::from_xyz(30., 0., 0.),
+            Velocity::linear(Vec2::X),
+            patrol,
+            EnemyKind::Walker,
+            EnemyStats::default(),
+            Health { current: 2., max: 5. },
+            brain,
+        ));
+        world.spawn((
+            Enemy,
+            EntityIid::new("turret_a"),
+            Transform::from_xyz(-40., 16., 0.),
+            GlobalTransform::from_xyz(-40., 16., 0.),
+            EnemyKind::Turret,
+            EnemyStats::for_kind(EnemyKind::Turret),
+            Health::new(3.),
+            EnemyBrain::default(),
+        ));
+        world.spawn((
+            Name::new("door_a"),
+            Door {
+                color: Some(ColorItem::Red),
+                locked: true,
+            },
+        ));
+        world
+    }
+
+    // 修改快照覆盖的所有状态
+    fn scramble(world: &mut World) {
+        world.insert_resource(LevelSelection::iid("level_b"));
+        world.resource_mut::<Score>().0 = 0;
+        world.resource_mut::<RouletteRng>().next_u64();
+        let mut query = world.query_filtered::<(&mut Transform, &mut Velocity), With<Player>>();
+        for (mut transform, mut velocity) in query.iter_mut(world) {
+            transform.translation = Vec3::new(-100., -100., 5.);
+            velocity.linvel = Vec2::ZERO;
+        }
+        for mut motion in world.query::<&mut PlayerMotion>().iter_mut(world) {
+            *motion = PlayerMotion::default();
+        }
+        for mut roulette in world.query::<&mut Roulette>().iter_mut(world) {
+            roulette.load(vec![RouletteSlot::new(5, 0)], 0, None);
+        }
+        let mut query = world.query::<(&mut Transform, &mut GlobalTransform, &mut Patrol, &mut Health, &mut EnemyBrain)>();
+        for (mut transform, mut global, mut patrol, mut health, mut brain) in query.iter_mut(world) {
+            transform.translation = Vec3::ZERO;
+            *global = GlobalTransform::IDENTITY;
+            patrol.index = 0;
+            patrol.forward = true;
+            patrol.wait = 0.;
+            health.current = health.max;
+            *brain = EnemyBrain::default();
+        }
+        // 炮台被消灭, 另外刷出一只新的敌人
+        let turret = world
+            .query::<(Entity, &EntityIid)>()
+            .iter(world)
+            .find(|(_, iid)| iid.to_string() == "turret_a")
+            .map(|(entity, _)| entity)
+            .unwrap();
+        world.despawn(turret);
+        world.spawn((Enemy, EnemyKind::Flyer, Transform::default(), GlobalTransform::default()));
+        for mut door in world.query::<&mut Door>().iter_mut(world) {
+            door.color = None;
+            door.locked = false;
+        }
+    }
+
+    #[test]
+    fn restore_returns_world_to_captured_moment() {
+        let mut world = setup_world();
+        let snapshot = capture(&mut world);
+        // 经过文件格式也要保持一致
+        let text = ron::to_string(&snapshot).unwrap();
+        let snapshot: RunSnapshot = ron::from_str(&text).unwrap();
+
+        scramble(&mut world);
+        assert_ne!(capture(&mut world), snapshot);
+        restore(&mut world, &snapshot);
+        assert_eq!(capture(&mut world), snapshot);
+
+        assert!(matches!(
+            world.resource::<LevelSelection>(),
+            LevelSelection::Iid(iid) if iid.to_string() == "level_a"
+        ));
+        assert_eq!(world.resource::<Score>().0, 120);
+
+        let (transform, velocity) = world
+            .query_filtered::<(&Transform, &Velocity), With<Player>>()
+            .single(&world);
+        assert_eq!(transform.translation, Vec3::new(10., 20., 5.));
+        assert_eq!(velocity.linvel, Vec2::new(1., -2.));
+
+        let motion = world.query::<&PlayerMotion>().single(&world);
+        assert_eq!((motion.vertical, motion.grounded_timer), (1.5, 0.25));
+
+        let roulette = world.query::<&Roulette>().single(&world);
+        let ids: Vec<u32> = roulette.get_all_items().iter().map(|slot| slot.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(roulette.get_cur_index(), 1);
+        // 转动中恢复时转动取消, 不会再停下触发结果
+        assert!(!roulette.is_spinning());
+
+        let (transform, patrol, health, brain) = world
+            .query::<(&Transform, &Patrol, &Health, &EnemyBrain)>()
+            .single(&world);
+        assert_eq!(transform.translation, Vec3::new(30., 0., 0.));
+        assert_eq!((patrol.index, patrol.forward, patrol.wait), (2, false, 0.5));
+        assert_eq!(health.current, 2.);
+        assert_eq!(brain.state, EnemyState::Stunned);
+
+        // 被消灭的炮台重新生成, 快照之后刷出的敌人被移除
+        let kinds: Vec<EnemyKind> = world.query_filtered::<&EnemyKind, With<Enemy>>().iter(&world).copied().collect();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains(&EnemyKind::Turret));
+        assert!(!kinds.contains(&EnemyKind::Flyer));
+
+        let door = world.query::<&Door>().single(&world);
+        assert_eq!((door.color, door.locked), (Some(ColorItem::Red), true));
+    }
+
+    #[test]
+    fn restored_rng_repeats_the_same_draws() {
+        let mut world = setup_world();
+        let snapshot = capture(&mut world);
+        let expected = world.resource_mut::<RouletteRng>().next_u64();
+        restore(&mut world, &snapshot);
+        assert_eq!(world.resource_mut::<RouletteRng>().next_u64(), expected);
+    }
+}
@@ -0,0 +1,388 @@
+// 刷怪点和波次
+// LDtk 中的 Spawner 实体按 wave.csv 的配置定时刷出敌人, 或在玩家进入区域后开始刷怪
+
+use bevy::prelude::*;
+use bevy_common_assets::csv::LoadedCsv;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::collision::Layer;
+use crate::comm::Health;
+use crate::config::WavesHandle;
+#[cfg(feature = "dev")]
+use crate::console::{console_arg, AddConsoleCommand, ConsoleResult};
+use crate::enemy::{Enemy, EnemyBrain, EnemyKind, EnemyStats, Patrol};
+use crate::player::Player;
+use crate::scene::{ColliderBundle, EnemyBundle};
+
+pub fn spawner_plugin(app: &mut App) {
+    app
+        .init_resource::<Difficulty>()
+        .init_resource::<RunProgress>()
+        .add_systems(OnEnter(crate::AppState::GameMain), reset_difficulty)
+        .add_systems(Update, (
+            update_difficulty.run_if(in_state(crate::AppState::GameMain)),
+            activate_spawner,
+            run_spawner,
+        ).chain());
+
+    #[cfg(feature = "dev")]
+    {
+        app.add_console_command("spawn", "spawn <mob|walker|flyer|turret> [count]: 在玩家旁边生成敌人", console_spawn);
+    }
+}
+
+// 每 DIFFICULTY_TIME 秒难度增加 DIFFICULTY_TIME_STEP
+const DIFFICULTY_TIME: f32 = 60.;
+const DIFFICULTY_TIME_STEP: f32 = 0.1;
+// 玩家每向上 DIFFICULTY_HEIGHT 像素难度增加 DIFFICULTY_HEIGHT_STEP
+const DIFFICULTY_HEIGHT: f32 = 256.;
+const DIFFICULTY_HEIGHT_STEP: f32 = 0.1;
+const DIFFICULTY_MAX: f32 = 3.;
+// 刷出的地面敌人左右巡逻的距离
+const SPAWN_PATROL_RANGE: f32 = 32.;
+
+// wave.csv 中的一行, 同一波可以有多行, 按顺序依次刷出
+#[derive(Serialize, Deserialize, Debug, TypePath, Asset, Clone)]
+pub struct WaveInfo {
+    pub wave: u32,
+    // 敌人类型, 与 LDtk 实体名一致: Mob, Flyer, Turret
+    pub kind: String,
+    pub count: u32,
+    // 两只敌人之间的间隔秒数
+    pub interval: f32,
+    pub health: f32,
+    pub speed: f32,
+}
+
+// 按配置文件中的顺序列出所有行
+fn wave_table<'a>(csv: &LoadedCsv<WaveInfo>, infos: &'a Assets<WaveInfo>) -> Vec<&'a WaveInfo> {
+    csv.rows.iter().filter_map(|row| infos.get(row)).collect()
+}
+
+// 某一波的所有行, 保持配置文件中的顺序
+fn wave_rows<'a>(table: &[&'a WaveInfo], wave: u32) -> Vec<&'a WaveInfo> {
+    table.iter().copied().filter(|info| info.wave == wave).collect()
+}
+
+fn first_wave(table: &[&WaveInfo]) -> Option<u32> {
+    table.iter().map(|info| info.wave).min()
+}
+
+fn next_wave(table: &[&WaveInfo], wave: u32) -> Option<u32> {
+    table.iter().map(|info| info.wave).filter(|w| *w > wave).min()
+}
+
+// 本局的游戏时间和起始高度, 每局开始时重置
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct RunProgress {
+    pub elapsed: f32,
+    pub start_height: Option<f32>,
+}
+
+// 难度系数, 随本局游戏时间和玩家高度增长
+// 敌人生命和速度乘以该系数, 刷怪间隔除以该系数
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Difficulty(pub f32);
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+impl Difficulty {
+    pub fn from_progress(elapsed: f32, height: f32) -> Self {
+        let value = 1.
+            + elapsed / DIFFICULTY_TIME * DIFFICULTY_TIME_STEP
+            + height.max(0.) / DIFFICULTY_HEIGHT * DIFFICULTY_HEIGHT_STEP;
+        Self(value.min(DIFFICULTY_MAX))
+    }
+}
+
+#[derive(Clone, Component, Debug, Default)]
+pub struct Spawner {
+    // 起始波次
+    pub wave: u32,
+    // 为 true 时玩家进入区域才开始刷怪, 否则关卡加载后立即开始
+    pub trigger: bool,
+    // 同时存活的最大数量, 0 表示不限制
+    pub max_alive: u32,
+    // 最后一波结束后从第一波重新开始
+    pub repeat: bool,
+    // 触发区域大小
+    pub size: Vec2,
+    pub active: bool,
+    pub finished: bool,
+    // 当前波次中正在刷的行
+    pub step: usize,
+    pub spawned: u32,
+    pub timer: Timer,
+}
+
+impl From<&EntityInstance> for Spawner {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let int_field = |name: &str| {
+            entity_instance
+                .get_maybe_int_field(name)
+                .ok()
+                .copied()
+                .flatten()
+        };
+        let bool_field = |name: &str| entity_instance.get_bool_field(name).ok().copied().unwrap_or(false);
+        let trigger = bool_field("trigger");
+        Spawner {
+            wave: int_field("wave").unwrap_or(1).max(0) as u32,
+            trigger,
+            max_alive: int_field("max_alive").unwrap_or(0).max(0) as u32,
+            repeat: bool_field("repeat"),
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+            active: !trigger,
+            ..default()
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct SpawnerBundle {
+    #[from_entity_instance]
+    pub spawner: Spawner,
+}
+
+// 由刷怪点刷出的敌人, 用于统计存活数量
+#[derive(Copy, Clone, Debug, Component)]
+pub struct SpawnedBy(pub Entity);
+
+fn reset_difficulty(mut progress: ResMut<RunProgress>, mut difficulty: ResMut<Difficulty>) {
+    *progress = RunProgress::default();
+    *difficulty = Difficulty::default();
+}
+
+fn update_difficulty(
+    time: Res<Time>,
+    query_player: Query<&GlobalTransform, With<Player>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut progress: ResMut<RunProgress>,
+) {
+    let Ok(transform) = query_player.get_single() else {
+        return;
+    };
+    progress.elapsed += time.delta_seconds();
+    let y = transform.translation().y;
+    let start = *progress.start_height.get_or_insert(y);
+    let value = Difficulty::from_progress(progress.elapsed, y - start);
+    if *difficulty != value {
+        *difficulty = value;
+    }
+}
+
+// 玩家进入触发区域后激活
+fn activate_spawner(
+    query_player: Query<&GlobalTransform, With<Player>>,
+    mut query: Query<(&mut Spawner, &GlobalTransform)>,
+) {
+    let Ok(player_transform) = query_player.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation().truncate();
+    for (mut spawner, transform) in query.iter_mut() {
+        if spawner.active || spawner.finished {
+            continue;
+        }
+        let offset = (player_pos - transform.translation().truncate()).abs();
+        if offset.x <= spawner.size.x / 2. && offset.y <= spawner.size.y / 2. {
+            spawner.active = true;
+        }
+    }
+}
+
+fn run_spawner(
+    mut cmds: Commands,
+    time: Res<Time>,
+    difficulty: Res<Difficulty>,
+    waves: Res<WavesHandle>,
+    wave_csv: Res<Assets<LoadedCsv<WaveInfo>>>,
+    wave_infos: Res<Assets<WaveInfo>>,
+    mut query: Query<(Entity, &mut Spawner, &Transform, Option<&Parent>)>,
+    query_spawned: Query<&SpawnedBy, With<Enemy>>,
+) {
+    // 配置还没加载完
+    let Some(csv) = wave_csv.get(&waves.0) else {
+        return;
+    };
+    let table = wave_table(csv, &wave_infos);
+    if table.is_empty() {
+        return;
+    }
+    for (entity, mut spawner, transform, parent) in query.iter_mut() {
+        if !spawner.active || spawner.finished {
+            continue;
+        }
+        let alive = query_spawned.iter().filter(|s| s.0 == entity).count() as u32;
+        let rows = wave_rows(&table, spawner.wave);
+
+        // 这一波刷完且全部被消灭后进入下一波
+        if spawner.step >= rows.len() {
+            if alive > 0 {
+                continue;
+            }
+            let next = next_wave(&table, spawner.wave)
+                .or_else(|| spawner.repeat.then(|| first_wave(&table)).flatten());
+            match next {
+                Some(wave) if !rows.is_empty() => {
+                    spawner.wave = wave;
+                    spawner.step = 0;
+                    spawner.spawned = 0;
+                }
+                _ => spawner.finished = true,
+            }
+            continue;
+        }
+
+        spawner.timer.tick(time.delta());
+        if !spawner.timer.finished() {
+            continue;
+        }
+        if spawner.max_alive > 0 && alive >= spawner.max_alive {
+            continue;
+        }
+
+        let info = rows[spawner.step];
+        let speed = (info.speed > 0.).then_some(info.speed * difficulty.0);
+        let enemy = spawn_enemy(
+            &mut cmds,
+            EnemyKind::from_identifier(&info.kind),
+            transform.translation,
+            info.health.max(1.) * difficulty.0,
+            speed,
+        );
+        cmds.entity(enemy).insert(SpawnedBy(entity));
+        // 和刷怪点放在同一个关卡下, 关卡卸载时一起清理
+        if let Some(parent) = parent {
+            cmds.entity(parent.get()).add_child(enemy);
+        }
+
+        spawner.spawned += 1;
+        if spawner.spawned >= info.count {
+            spawner.step += 1;
+            spawner.spawned = 0;
+        }
+        spawner.timer = Timer::from_seconds(info.interval / difficulty.0, TimerMode::Once);
+    }
+}
+
+// 在运行时生成一个敌人, speed 为 None 时使用该类型的默认速度
+pub fn spawn_enemy(
+    cmds: &mut Commands,
+    kind: EnemyKind,
+    translation: Vec3,
+    health: f32,
+    speed: Option<f32>,
+) -> Entity {
+    let mut stats = EnemyStats::for_kind(kind);
+    if let Some(speed) = speed {
+        stats.speed = speed;
+    }
+
+    let (collider, rigid_body, color) = match kind {
+        EnemyKind::Turret => (Collider::cuboid(8., 8.), RigidBody::Fixed, Color::ORANGE_RED),
+        EnemyKind::Flyer => (Collider::cuboid(5., 5.), RigidBody::KinematicVelocityBased, Color::VIOLET),
+        EnemyKind::Walker => (Collider::cuboid(5., 5.), RigidBody::KinematicVelocityBased, Color::MAROON),
+    };
+    let size = match kind {
+        EnemyKind::Turret => Vec2::splat(16.),
+        _ => Vec2::splat(10.),
+    };
+
+    let mut enemy = cmds.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_translation(translation),
+            ..default()
+        },
+        ColliderBundle {
+            collider,
+            rigid_body,
+            rotation_constraints: LockedAxes::ROTATION_LOCKED,
+            ..default()
+        }.with_layer(Layer::Enemy),
+        Enemy,
+        EnemyBundle {
+            kind,
+            stats,
+            brain: EnemyBrain::default(),
+            health: Health::new(health),
+            ..default()
+        },
+        Name::new(format!("spawned_{:?}", kind)),
+    ));
+    if kind == EnemyKind::Walker {
+        let origin = translation.truncate();
+        enemy.insert(Patrol::new(vec![
+            origin - Vec2::X * SPAWN_PATROL_RANGE,
+            origin + Vec2::X * SPAWN_PATROL_RANGE,
+        ]));
+    }
+    enemy.id()
+}
+
+#[cfg(feature = "dev")]
+fn console_spawn(world: &mut World, args: &[&str]) -> ConsoleResult {
+    let kind = match args.first().map(|arg| arg.to_lowercase()).as_deref() {
+        Some("mob") | Some("walker") => EnemyKind::Walker,
+        Some("flyer") => EnemyKind::Flyer,
+        Some("turret") => EnemyKind::Turret,
+        Some(other) => return Err(format!("unknown enemy kind: {}", other)),
+        None => return Err("missing <kind>".to_string()),
+    };
+    let count: u32 = if args.len() > 1 { console_arg(args, 1, "count")? } else { 1 };
+    let player = world
+        .query_filtered::<&Transform, With<Player>>()
+        .iter(world)
+        .next()
+        .map(|transform| transform.translation)
+        .ok_or("no player")?;
+
+    let mut queue = bevy::ecs::system::CommandQueue::default();
+    let mut cmds = Commands::new(&mut queue, world);
+    for i in 0..count {
+        // 在玩家前上方排开, 避免生成在玩家身上
+        let offset = Vec3::new(64. + 24. * i as f32, 32., 0.);
+        spawn_enemy(&mut cmds, kind, player + offset, Health::default().max, None);
+    }
+    queue.apply(world);
+    Ok(format!("spawned {:?} x{}", kind, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(wave: u32, kind: &str) -> WaveInfo {
+        WaveInfo {
+            wave,
+            kind: kind.to_string(),
+            count: 1,
+            interval: 1.,
+            health: 1.,
+            speed: 0.,
+        }
+    }
+
+    #[test]
+    fn waves_follow_file_order() {
+        let rows = [row(2, "Flyer"), row(1, "Turret"), row(2, "Mob"), row(1, "Mob")];
+        let table: Vec<&WaveInfo> = rows.iter().collect();
+        let kinds = |wave| wave_rows(&table, wave).iter().map(|info| info.kind.as_str()).collect::<Vec<_>>();
+        assert_eq!(kinds(1), ["Turret", "Mob"]);
+        assert_eq!(kinds(2), ["Flyer", "Mob"]);
+        assert_eq!(first_wave(&table), Some(1));
+        assert_eq!(next_wave(&table, 1), Some(2));
+        assert_eq!(next_wave(&table, 2), None);
+    }
+}
@@ -3,6 +3,8 @@ use bevy_rapier2d::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy::utils::{HashMap, HashSet};
 
+use crate::collision::Layer;
+
 #[derive(Component, Clone, Debug, Default)]
 pub struct Wall;
 
@@ -155,6 +157,7 @@ fn wall_collision(
                                     / 2.,
                             ))
                             .insert(Collidable)
+                            .insert(Layer::Wall.groups())
                             .insert(ActiveEvents::COLLISION_EVENTS)
                             .insert(RigidBody::Fixed)
                             .insert(Friction::new(1.0))